@@ -1,9 +1,18 @@
 extern crate mcts;
+extern crate rand;
 
 use std::fmt;
+use std::fs;
 use std::io;
+use std::io::BufRead;
+use std::io::Write;
 use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
 use mcts::*;
+use rand::Rng;
 
 use std::str::FromStr;
 
@@ -28,35 +37,252 @@ impl fmt::Display for C4Cell {
     }
 }
 
+/// The size of the board. The bitboards index cells as `row * COLS + col`, from the top left.
+/// The tables below are derived from these, but only the standard 7x6 board is played and
+/// tested.
+const ROWS: u8 = 6;
+const COLS: u8 = 7;
+const CELLS: usize = ROWS as usize * COLS as usize;
+/// The top row's cells, which are the low `COLS` bits of a bitboard.
+const TOP_ROW: u8 = (1 << COLS) - 1;
+/// The columns up to and including the middle one, which mirror all the others.
+const LEFT_HALF: u8 = (1 << COLS.div_ceil(2)) - 1;
+
+// The cells must fit in the `u64` bitboards, and the columns in the `u8` of moves.
+const _: () = assert!(CELLS <= 64 && COLS <= 8);
+
 #[derive(Clone)]
 struct C4State {
     xs: u64,
     os: u64,
     next: Player,
+    /// Whether either player has four in a row, cached by `do_action` so that generating moves
+    /// doesn't rescan the board.
+    won: bool,
+    /// The Zobrist hash of the position, kept up to date by `play` and `do_action`.
+    hash: u64,
+    /// Whether every line of four holds pieces of both players, so that nobody can win.
+    dead: bool,
+    /// Whether `ordered_actions` skips moves that mirror others; see `is_symmetric`.
+    prune_mirrors: bool,
+    /// The `(row, col)` of the last piece placed, which `Display` brackets.
+    last_move: Option<(u8, u8)>,
+}
+
+/// A step of the splitmix64 generator, used to fill the Zobrist table at compile time.
+const fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn zobrist_table() -> [[u64; CELLS]; 2] {
+    let mut table = [[0; CELLS]; 2];
+    let mut i = 0;
+    while i < 2 * CELLS {
+        table[i / CELLS][i % CELLS] = splitmix(i as u64);
+        i += 1;
+    }
+    table
+}
+
+/// The `(row, col)` steps along a line: across, down and down each diagonal.
+const STEPS: [(i32, i32); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+/// The mask of the line of four from `cell` along `STEPS[s]`, or 0 if it leaves the board.
+const fn line_mask(cell: usize, s: usize) -> u64 {
+    let (rows, cols) = (ROWS as i32, COLS as i32);
+    let (dr, dc) = STEPS[s];
+    let (row, col) = (cell as i32 / cols, cell as i32 % cols);
+    let (end_row, end_col) = (row + 3 * dr, col + 3 * dc);
+    if end_row >= rows || end_col < 0 || end_col >= cols {
+        return 0;
+    }
+    let mut mask = 0;
+    let mut i = 0;
+    while i < 4 {
+        mask |= 1 << ((row + i * dr) * cols + col + i * dc);
+        i += 1;
+    }
+    mask
+}
+
+const fn count_lines() -> usize {
+    let mut n = 0;
+    let mut i = 0;
+    while i < 4 * CELLS {
+        if line_mask(i % CELLS, i / CELLS) != 0 {
+            n += 1;
+        }
+        i += 1;
+    }
+    n
+}
+
+/// How many lines of four fit on the board: 69 on the standard one, 24 of them horizontal, 21
+/// vertical and 12 along each diagonal.
+const NUM_LINES: usize = count_lines();
+
+/// Every line of four cells on the board, as masks.
+const fn line_masks() -> [u64; NUM_LINES] {
+    let mut masks = [0; NUM_LINES];
+    let mut n = 0;
+    let mut i = 0;
+    while i < 4 * CELLS {
+        let mask = line_mask(i % CELLS, i / CELLS);
+        if mask != 0 {
+            masks[n] = mask;
+            n += 1;
+        }
+        i += 1;
+    }
+    masks
 }
 
+const LINES: [u64; NUM_LINES] = line_masks();
+
+/// A random key per player per cell, XORed into the hash when that player fills that cell.
+const ZOBRIST: [[u64; CELLS]; 2] = zobrist_table();
+/// XORed into the hash whenever the turn passes.
+const SIDE_TO_MOVE: u64 = splitmix(2 * CELLS as u64);
+
 impl fmt::Display for C4State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for r in 0..6 {
-            write!(f, "|")?;
-            write!(f, "{}", self.get(r, 0))?;
-            for c in 1..7 {
-                write!(f, " ")?;
-                write!(f, "{}", self.get(r, c))?;
+        let (rows, cols) = (self.rows(), self.cols());
+        for r in 0..rows {
+            // The gap left of column `c`, which brackets the last move instead of spacing it.
+            let gap = |c: u8| match self.last_move {
+                Some((row, col)) if row == r && col == c => '(',
+                Some((row, col)) if row == r && col + 1 == c => ')',
+                _ if c == 0 || c == cols => '|',
+                _ => ' ',
+            };
+            for c in 0..cols {
+                write!(f, "{}{}", gap(c), self.get(r, c))?;
+            }
+            writeln!(f, "{}", gap(cols))?;
+        }
+        let border = format!("+{}+", "-".repeat(2 * cols as usize - 1));
+        let labels: Vec<String> = (0..cols).map(|c| c.to_string()).collect();
+        writeln!(f, "{}", border)?;
+        writeln!(f, "|{}|", labels.join(" "))?;
+        write!(f, "{}", border)
+    }
+}
+
+/// Parses a position written as its rows from top to bottom, separated by `/`, with `X`,
+/// `O` and `.` for empty cells, e.g. `......./......./......./......./...O.../..XX...`. Any
+/// position reachable in a game is accepted, with the player to move inferred from the counts.
+impl FromStr for C4State {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (rows, cols) = (ROWS as usize, COLS as usize);
+        let lines: Vec<&str> = s.trim().split('/').collect();
+        if lines.len() != rows {
+            return Err(format!("Expected {} rows, found {}", rows, lines.len()));
+        }
+        let (mut xs, mut os) = (0u64, 0u64);
+        for (row, line) in lines.iter().enumerate() {
+            if line.chars().count() != cols {
+                return Err(format!("Row {} should have {} cells: `{}`", row, cols, line));
+            }
+            for (col, cell) in line.chars().enumerate() {
+                match cell {
+                    'X' => xs |= 1 << (row * cols + col),
+                    'O' => os |= 1 << (row * cols + col),
+                    '.' => {}
+                    _ => return Err(format!("Unexpected cell `{}` in row {}", cell, row)),
+                }
             }
-            writeln!(f, "|")?;
         }
-        writeln!(f, "+-------------+")?;
-        writeln!(f, "|0 1 2 3 4 5 6|")?;
-        write!(f, "+-------------+")
+        C4State::from_pieces(xs, os)
     }
 }
 
 impl C4State {
+    /// The position with `xs` and `os` as the players' pieces, if it can happen in a game.
+    fn from_pieces(xs: u64, os: u64) -> Result<C4State, String> {
+        if xs & os != 0 {
+            return Err("X and O share a cell".to_string());
+        }
+        if (xs | os) >> CELLS != 0 {
+            return Err("A piece lies outside the board".to_string());
+        }
+        let mut board = C4State { xs, os, ..C4State::initial() };
+        for row in 0..ROWS - 1 {
+            for col in 0..COLS {
+                let below = board.get(row + 1, col);
+                if board.get(row, col) != C4Cell::Blank && below == C4Cell::Blank {
+                    return Err(format!("The piece at row {} column {} is floating", row, col));
+                }
+            }
+        }
+        let (xs, os) = (board.xs.count_ones(), board.os.count_ones());
+        board.next = match xs.checked_sub(os) {
+            Some(0) => Player::P1,
+            Some(1) => Player::P2,
+            _ => return Err(format!("{} Xs and {} Os can't happen in a game", xs, os)),
+        };
+        board.hash = board.full_hash();
+        // The player to move moved before the last move, so they can't have won already.
+        if board.has_won(board.next) {
+            return Err(format!("{:?} won before the last move", board.next));
+        }
+        board.won = board.has_won(board.next.other());
+        board.dead = !board.won && board.is_drawn_dead();
+        Ok(board)
+    }
+    /// The position in the form `FromStr` parses: its rows from the top, separated by `/`.
+    fn to_rows(&self) -> String {
+        let rows: Vec<String> = (0..self.rows())
+            .map(|r| {
+                (0..self.cols())
+                    .map(|c| match self.get(r, c) {
+                        C4Cell::X => 'X',
+                        C4Cell::O => 'O',
+                        C4Cell::Blank => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+        rows.join("/")
+    }
+    /// A fixed-size encoding for sending positions over the wire: each player's pieces as a
+    /// little-endian bitboard, then the index of the player to move.
+    #[allow(dead_code)]
+    fn to_bytes(&self) -> [u8; 17] {
+        let mut bytes = [0; 17];
+        bytes[..8].copy_from_slice(&self.xs.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.os.to_le_bytes());
+        bytes[16] = self.next.index() as u8;
+        bytes
+    }
+    /// Decodes `to_bytes`, rejecting anything that isn't a position from a game.
+    #[allow(dead_code)]
+    fn from_bytes(bytes: &[u8; 17]) -> Result<C4State, String> {
+        let mut xs = [0; 8];
+        let mut os = [0; 8];
+        xs.copy_from_slice(&bytes[..8]);
+        os.copy_from_slice(&bytes[8..16]);
+        let board = C4State::from_pieces(u64::from_le_bytes(xs), u64::from_le_bytes(os))?;
+        if bytes[16] as usize != board.next.index() {
+            return Err(format!("{:?} can't be to move here", bytes[16]));
+        }
+        Ok(board)
+    }
+    fn rows(&self) -> u8 {
+        ROWS
+    }
+
+    fn cols(&self) -> u8 {
+        COLS
+    }
+
     fn get(&self, row: u8, col: u8) -> C4Cell {
-        if ((self.os >> (row * 7 + col)) & 1) == 1 {
+        if ((self.os >> (row * COLS + col)) & 1) == 1 {
             C4Cell::O
-        } else if ((self.xs >> (row * 7 + col)) & 1) == 1 {
+        } else if ((self.xs >> (row * COLS + col)) & 1) == 1 {
             C4Cell::X
         } else {
             C4Cell::Blank
@@ -64,12 +290,105 @@ impl C4State {
     }
     fn play(&mut self, row: u8, col: u8, player: Player) {
         match player {
-            Player::P1 => self.xs |= 1 << (row * 7 + col),
-            Player::P2 => self.os |= 1 << (row * 7 + col),
+            Player::P1 => self.xs |= 1 << (row * COLS + col),
+            Player::P2 => self.os |= 1 << (row * COLS + col),
+        }
+        self.hash ^= ZOBRIST[player.index()][(row * COLS + col) as usize];
+    }
+    /// The Zobrist hash computed from scratch, to check the incremental one against.
+    fn full_hash(&self) -> u64 {
+        let mut hash = if self.next == Player::P2 { SIDE_TO_MOVE } else { 0 };
+        for (board, keys) in [self.xs, self.os].iter().zip(ZOBRIST.iter()) {
+            for (cell, key) in keys.iter().enumerate() {
+                if (board >> cell) & 1 == 1 {
+                    hash ^= key;
+                }
+            }
         }
+        hash
     }
     fn full(&self) -> bool {
-        (self.xs | self.os).count_ones() == 42
+        self.moves_played() == CELLS
+    }
+    /// Whether the position is its own left-right mirror image. Then playing column `c` is
+    /// equivalent to playing `COLS - 1 - c`, so only the `LEFT_HALF` need to be searched. This
+    /// only happens in the opening: once one side breaks the symmetry it can't return.
+    fn is_symmetric(&self) -> bool {
+        let cols = COLS as usize;
+        let mirrored = |board: u64| {
+            (0..CELLS).fold(0u64, |m, i| {
+                m | ((board >> i) & 1) << (i / cols * cols + cols - 1 - i % cols)
+            })
+        };
+        mirrored(self.xs) == self.xs && mirrored(self.os) == self.os
+    }
+    /// Whether neither player can still make four in a row, however the game goes on.
+    fn is_drawn_dead(&self) -> bool {
+        LINES.iter().all(|&line| line & self.xs != 0 && line & self.os != 0)
+    }
+    fn moves_played(&self) -> usize {
+        (self.xs | self.os).count_ones() as usize
+    }
+    /// Whether the piece `player` has at `(row, col)` is part of four in a row. Any line made by
+    /// the last move passes through its cell, so this is all `do_action` needs to check.
+    fn last_move_wins(&self, row: u8, col: u8, player: Player) -> bool {
+        let board = match player {
+            Player::P1 => self.xs,
+            Player::P2 => self.os,
+        };
+        let (rows, cols) = (ROWS as i8, COLS as i8);
+        let owns = |r: i8, c: i8| {
+            (0..rows).contains(&r) && (0..cols).contains(&c) && (board >> (r * cols + c)) & 1 == 1
+        };
+        [(0, 1), (1, 0), (1, 1), (1, -1)].iter().any(|&(dr, dc)| {
+            let run = |sign: i8| {
+                (1..4)
+                    .take_while(|k| owns(row as i8 + sign * k * dr, col as i8 + sign * k * dc))
+                    .count()
+            };
+            run(1) + run(-1) >= 3
+        })
+    }
+    /// The `(row, col)` cells of a four in a row belonging to `player`, if there is one.
+    fn winning_line(&self, player: Player) -> Option<[(u8, u8); 4]> {
+        let owner = match player {
+            Player::P1 => C4Cell::X,
+            Player::P2 => C4Cell::O,
+        };
+        let (rows, cols) = (ROWS as i8, COLS as i8);
+        let owns = |r: i8, c: i8| {
+            (0..rows).contains(&r) && (0..cols).contains(&c) && self.get(r as u8, c as u8) == owner
+        };
+        let mut line = None;
+        'search: for row in 0..rows {
+            for col in 0..cols {
+                for &(dr, dc) in [(0, 1), (1, 0), (1, 1), (1, -1)].iter() {
+                    if (0..4).all(|k| owns(row + k * dr, col + k * dc)) {
+                        let mut cells = [(0, 0); 4];
+                        for (k, cell) in cells.iter_mut().enumerate() {
+                            let k = k as i8;
+                            *cell = ((row + k * dr) as u8, (col + k * dc) as u8);
+                        }
+                        line = Some(cells);
+                        break 'search;
+                    }
+                }
+            }
+        }
+        debug_assert_eq!(line.is_some(), self.has_won(player));
+        line
+    }
+    /// Renders the board with the pieces of `line` drawn as `*`.
+    fn render_winning_line(&self, line: &[(u8, u8); 4]) -> String {
+        let mut rows: Vec<Vec<char>> = self.to_string()
+            .lines()
+            .map(|l| l.chars().collect())
+            .collect();
+        for &(row, col) in line.iter() {
+            rows[row as usize][2 * col as usize + 1] = '*';
+        }
+        let rows: Vec<String> = rows.into_iter().map(|r| r.into_iter().collect()).collect();
+        rows.join("\n")
     }
 }
 
@@ -82,6 +401,11 @@ impl State for C4State {
             xs: 0,
             os: 0,
             next: Player::P1,
+            won: false,
+            dead: false,
+            hash: 0,
+            prune_mirrors: false,
+            last_move: None,
         }
     }
 
@@ -89,51 +413,123 @@ impl State for C4State {
         self.next
     }
 
+    /// Points each label at its column from below the board.
+    fn render_with_annotations(&self, annotations: &[(Self::Action, String)]) -> String {
+        let mut rendered = self.render();
+        for &(col, ref label) in annotations {
+            let indent = " ".repeat(2 * col as usize + 1);
+            rendered.push_str(&format!("\n{}^ {}", indent, label));
+        }
+        rendered
+    }
+
+    /// Panics if `col` is full: such moves are never generated, and treating one as anything
+    /// else would feed the search a position that can't occur.
     fn do_action(&mut self, col: Self::Action) -> Outcome<Self::Actions> {
-        for row in (0..6).rev() {
+        for row in (0..ROWS).rev() {
             if self.get(row, col) == C4Cell::Blank {
                 let player = self.next;
                 self.play(row, col, player);
                 self.next = self.next.other();
-                return if self.has_won(player) {
+                self.hash ^= SIDE_TO_MOVE;
+                self.last_move = Some((row, col));
+                debug_assert_eq!(self.hash, self.full_hash());
+                self.won = self.last_move_wins(row, col, player);
+                debug_assert_eq!(self.won, self.has_won(player));
+                self.dead = !self.won && self.is_drawn_dead();
+                return if self.won {
                     Outcome::from_player(player)
-                } else if self.full() {
+                } else if self.full() || self.dead {
                     Outcome::Draw
-                }else {
+                } else {
                     Outcome::Actions(self.valid_actions(self.next))
                 };
             }
         }
-        Outcome::Draw
+        panic!("Column {} is full", col)
     }
 
     fn valid_actions(&self, _: Player) -> Self::Actions {
-        let mut bitvec = 0;
-        if !self.has_won(Player::P1) && !self.has_won(Player::P2) {
-            for i in (0..7).filter(|col| self.get(0, *col) == C4Cell::Blank) {
-                bitvec |= 1u8 << i;
+        // A column is open while its top cell, bit `col` of the top row, is empty.
+        let bitvec = if self.won || self.dead {
+            0
+        } else {
+            !(self.xs | self.os) as u8 & TOP_ROW
+        };
+        C4Actions { bitvec, center_out: false }
+    }
+
+    fn ordered_actions(&self, player: Player) -> Self::Actions {
+        let mut actions = C4Actions {
+            center_out: true,
+            ..self.valid_actions(player)
+        };
+        if self.prune_mirrors && self.is_symmetric() {
+            actions.bitvec &= LEFT_HALF;
+        }
+        actions
+    }
+
+    /// Samples columns in proportion to `ROLLOUT_WEIGHTS`, since central pieces take part in
+    /// more lines.
+    fn rollout_action<R: Rng>(&self, actions: Self::Actions, rng: &mut R) -> Self::Action {
+        let legal = |col: &u8| actions.bitvec & (1 << col) != 0;
+        let total: u32 = (0..COLS).filter(legal).map(|c| ROLLOUT_WEIGHTS[c as usize]).sum();
+        let mut target = rng.gen_range(0, total);
+        for col in (0..COLS).filter(legal) {
+            let weight = ROLLOUT_WEIGHTS[col as usize];
+            if target < weight {
+                return col;
             }
+            target -= weight;
         }
-        C4Actions { bitvec }
+        unreachable!("no legal column to play out")
+    }
+
+    fn hash_key(&self) -> Option<u64> {
+        Some(self.hash)
+    }
+
+    /// Every move fills a cell, so no game outlasts the board.
+    fn max_playout_moves() -> usize {
+        CELLS
     }
 
     fn has_won(&self, player: Player) -> bool {
         let streak = 4;
-        let rows = 6;
-        let cols = 7;
-        let col_win = 0b0000000_0000000_0000001_0000001_0000001_0000001;
-        let row_win = 0b0000000_0000000_0000000_0000000_0000000_0001111;
-        let d1_win = 0b0000000_0000000_0001000_0000100_0000010_0000001;
-        let d2_win = 0b0000000_0000000_0000001_0000010_0000100_0001000;
+        let rows = ROWS as usize;
+        let cols = COLS as usize;
+        // The lines of four from the top left, e.g. `col_win` has a bit in each of the first
+        // four rows.
+        let line = |step: usize, first: usize| {
+            (0..streak).fold(0u64, |mask, k| mask | 1 << (first + k * step))
+        };
+        let col_win = line(cols, 0);
+        let row_win = line(1, 0);
+        let d1_win = line(cols + 1, 0);
+        let d2_win = line(cols - 1, streak - 1);
         let board = match player {
             Player::P1 => self.xs,
             Player::P2 => self.os,
         };
 
+        // A mask shifted off the top of the board would lose cells and could match a partial
+        // line, so make sure the board and every shifted mask fit.
+        debug_assert!(rows * cols <= 64, "the board doesn't fit in a u64");
+        let board_mask = u64::MAX >> (64 - rows * cols);
+        let shifted = |mask: u64, shift: usize| {
+            debug_assert!(
+                shift < 64 && (mask << shift) >> shift == mask && (mask << shift) & !board_mask == 0,
+                "win mask {:#b} shifted by {} leaves the board",
+                mask,
+                shift
+            );
+            mask << shift
+        };
 
         // Column wins
         for s in 0..(cols * (rows - streak + 1)) {
-            let win = col_win << s;
+            let win = shifted(col_win, s);
             if (board ^ win) & win == 0 {
                 return true;
             }
@@ -142,7 +538,7 @@ impl State for C4State {
         // Check row wins
         for r in 0..(rows) {
             for c in 0..(cols - streak + 1) {
-                let win = row_win << (r * 7 + c);
+                let win = shifted(row_win, r * cols + c);
                 if (board ^ win) & win == 0 {
                     return true;
                 }
@@ -152,11 +548,11 @@ impl State for C4State {
         // Check for diagonal wins
         for r in 0..(rows - streak + 1) {
             for c in 0..(cols - streak + 1) {
-                let win = d1_win << (r * 7 + c);
+                let win = shifted(d1_win, r * cols + c);
                 if (board ^ win) & win == 0 {
                     return true;
                 }
-                let win = d2_win << (r * 7 + c);
+                let win = shifted(d2_win, r * cols + c);
                 if (board ^ win) & win == 0 {
                     return true;
                 }
@@ -166,28 +562,69 @@ impl State for C4State {
     }
 }
 
-#[derive(Clone)]
+/// The relative chance of each column being played in a playout: 1 at the edges, rising by 1
+/// per column towards the center.
+const ROLLOUT_WEIGHTS: [u32; COLS as usize] = rollout_weights();
+
+const fn rollout_weights() -> [u32; COLS as usize] {
+    let mut weights = [0; COLS as usize];
+    let mut col = 0;
+    while col < COLS {
+        let from_edge = if col < COLS - 1 - col { col } else { COLS - 1 - col };
+        weights[col as usize] = from_edge as u32 + 1;
+        col += 1;
+    }
+    weights
+}
+
+/// Columns from the center outwards, which is roughly strongest first, taking the left one of
+/// each pair first: 3, 2, 4, 1, 5, 0, 6 on the standard board.
+const CENTER_OUT: [u8; COLS as usize] = center_out();
+
+const fn center_out() -> [u8; COLS as usize] {
+    let mut order = [0; COLS as usize];
+    let mut n = 0;
+    // Twice the distance from the center, to keep it whole when `COLS` is even.
+    let mut distance = 0;
+    while n < COLS as usize {
+        let mut col = 0;
+        while col < COLS {
+            if (2 * col as i32 - (COLS as i32 - 1)).abs() == distance {
+                order[n] = col;
+                n += 1;
+            }
+            col += 1;
+        }
+        distance += 1;
+    }
+    order
+}
+
+#[derive(Clone, Default)]
 struct C4Actions {
     bitvec: u8,
+    center_out: bool,
 }
 
 impl fmt::Debug for C4Actions {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:07b}", self.bitvec)
-    }
-}
-
-impl Default for C4Actions {
-    fn default() -> Self {
-        C4Actions { bitvec: 0 }
+        write!(f, "{:01$b}", self.bitvec, COLS as usize)
     }
 }
 
 impl Iterator for C4Actions {
     type Item = u8;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.center_out {
+            let bitvec = self.bitvec;
+            let ans = CENTER_OUT.iter().cloned().find(|c| bitvec & (1u8 << c) != 0);
+            if let Some(c) = ans {
+                self.bitvec &= !(1u8 << c);
+            }
+            return ans;
+        }
         let ans = self.bitvec.trailing_zeros() as u8;
-        if ans < 7 {
+        if ans < COLS {
             self.bitvec &= !(1u8 << ans);
             Some(ans)
         } else {
@@ -202,78 +639,1133 @@ impl Iterator for C4Actions {
 
 impl ExactSizeIterator for C4Actions {}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+    /// A board holding X's pieces at `cells` and nothing else.
+    fn with_xs(cells: &[(i8, i8)]) -> C4State {
+        let xs = cells.iter().fold(0, |xs, &(r, c)| xs | 1 << (r * COLS as i8 + c));
+        C4State { xs, ..C4State::initial() }
+    }
+
+    /// Every line of four on the board, as the cells it covers.
+    fn all_lines() -> Vec<[(i8, i8); 4]> {
+        let mut lines = Vec::new();
+        for row in 0..ROWS as i8 {
+            for col in 0..COLS as i8 {
+                for &(dr, dc) in DIRECTIONS.iter() {
+                    let cells = [0, 1, 2, 3].map(|k| (row + k * dr, col + k * dc));
+                    let (end_row, end_col) = cells[3];
+                    if end_row < ROWS as i8 && (0..COLS as i8).contains(&end_col) {
+                        lines.push(cells);
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    /// Plays `games` games of random moves, seeded with `seed`, calling `check` after each move.
+    fn play_random_games<F: FnMut(&C4State)>(games: usize, seed: u64, mut check: F) {
+        let mut rng = seeded_rng(seed);
+        for _ in 0..games {
+            let mut board = C4State::initial();
+            while let Outcome::Actions(actions) = board.outcome() {
+                let col = board.rollout_action(actions, &mut rng);
+                board.do_action(col);
+                check(&board);
+            }
+        }
+    }
+
+    #[test]
+    fn every_line_of_four_wins() {
+        let lines = all_lines();
+        assert_eq!(lines.len(), 69);
+        let masks: Vec<u64> = lines
+            .iter()
+            .map(|cells| cells.iter().fold(0, |m, &(r, c)| m | 1 << (r * COLS as i8 + c)))
+            .collect();
+        assert!(masks.iter().all(|mask| LINES.contains(mask)) && LINES.len() == masks.len());
+        for cells in lines {
+            let board = with_xs(&cells);
+            assert!(board.has_won(Player::P1), "{:?}", cells);
+            assert!(!board.has_won(Player::P2), "{:?}", cells);
+            for &(row, col) in cells.iter() {
+                assert!(board.last_move_wins(row as u8, col as u8, Player::P1), "{:?}", cells);
+            }
+            let line = board.winning_line(Player::P1).unwrap();
+            assert_eq!(line, cells.map(|(r, c)| (r as u8, c as u8)));
+        }
+    }
+
+    #[test]
+    fn three_in_a_row_does_not_win() {
+        for cells in all_lines() {
+            for skip in 0..4 {
+                let three: Vec<_> = (0..4).filter(|&k| k != skip).map(|k| cells[k]).collect();
+                let board = with_xs(&three);
+                assert!(!board.has_won(Player::P1), "{:?}", three);
+                assert_eq!(board.winning_line(Player::P1), None);
+                for &(row, col) in three.iter() {
+                    assert!(!board.last_move_wins(row as u8, col as u8, Player::P1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn last_move_wins_agrees_with_a_full_scan() {
+        play_random_games(2000, 542, |board| {
+            let (row, col) = board.last_move.unwrap();
+            let mover = board.next.other();
+            assert_eq!(board.last_move_wins(row, col, mover), board.has_won(mover), "\n{}", board);
+            assert_eq!(board.won, board.has_won(mover));
+        });
+    }
+
+    #[test]
+    fn incremental_hash_matches_the_full_hash() {
+        play_random_games(2000, 566, |board| {
+            assert_eq!(board.hash, board.full_hash(), "\n{}", board);
+        });
+    }
+
+    #[test]
+    fn rows_round_trip_through_from_str() {
+        play_random_games(200, 578, |board| {
+            let parsed = C4State::from_str(&board.to_rows()).unwrap();
+            assert_eq!((parsed.xs, parsed.os, parsed.next), (board.xs, board.os, board.next));
+            assert_eq!((parsed.won, parsed.dead), (board.won, board.dead));
+        });
+    }
+
+    #[test]
+    fn saved_games_load_from_their_starting_position() {
+        let start = C4State::from_str("......./......./......./......./......./...X...").unwrap();
+        let moves = [4, 2, 4];
+        let board = replay(start.clone(), &moves).unwrap();
+        let path = env::temp_dir().join(format!("c4ai-save-test-{}", process::id()));
+        let path = path.to_str().unwrap();
+        save(path, &start, &moves, &board).unwrap();
+        let loaded = load(path);
+        fs::remove_file(path).unwrap();
+        let (loaded_start, loaded_moves) = loaded.unwrap();
+        assert_eq!((loaded_start.xs, loaded_start.os), (start.xs, start.os));
+        assert_eq!(loaded_moves, moves);
+        let resumed = replay(loaded_start, &loaded_moves).unwrap();
+        assert_eq!((resumed.xs, resumed.os, resumed.next), (board.xs, board.os, board.next));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        play_random_games(200, 606, |board| {
+            let decoded = C4State::from_bytes(&board.to_bytes()).unwrap();
+            assert_eq!((decoded.xs, decoded.os, decoded.next), (board.xs, board.os, board.next));
+            assert_eq!((decoded.won, decoded.dead), (board.won, board.dead));
+            assert_eq!(decoded.hash, board.hash);
+        });
+    }
+
+    #[test]
+    fn from_bytes_rejects_impossible_positions() {
+        let bottom_left = 1u64 << ((ROWS - 1) * COLS);
+        let encode = |xs: u64, os: u64, next: u8| {
+            let board = C4State { xs, os, ..C4State::initial() };
+            let mut bytes = board.to_bytes();
+            bytes[16] = next;
+            bytes
+        };
+        assert!(C4State::from_bytes(&encode(bottom_left, 0, 1)).is_ok());
+        // Both players in the same cell.
+        assert!(C4State::from_bytes(&encode(bottom_left, bottom_left, 1)).is_err());
+        // A piece in the top left corner with nothing below it.
+        assert!(C4State::from_bytes(&encode(1, 0, 1)).is_err());
+        // X has moved, so it's O's turn.
+        assert!(C4State::from_bytes(&encode(bottom_left, 0, 0)).is_err());
+        assert!(C4State::from_bytes(&encode(bottom_left, 0, 2)).is_err());
+        // Bits past the last cell.
+        assert!(C4State::from_bytes(&encode(bottom_left | 1 << CELLS, 0, 1)).is_err());
+    }
+
+    /// Every line of four in this position holds both colors, though six cells are still empty.
+    const DEAD_DRAW: &str = "X..OX../X.OOXX./OOXXOOX/XXOOXXO/OOXXOOX/OOXXOOX";
+
+    #[test]
+    fn dead_draws_end_the_game() {
+        let board = C4State::from_str(DEAD_DRAW).unwrap();
+        assert!(board.is_drawn_dead() && board.dead);
+        assert!(!board.full());
+        assert_eq!(board.valid_actions(board.next).len(), 0);
+        assert!(matches!(board.outcome(), Outcome::Draw));
+
+        // Before O's last move, X could still make four along the top row.
+        let before = "X...X../X.OOXX./OOXXOOX/XXOOXXO/OOXXOOX/OOXXOOX";
+        let mut board = C4State::from_str(before).unwrap();
+        assert!(!board.is_drawn_dead() && !board.dead);
+        assert!(matches!(board.do_action(3), Outcome::Draw));
+        assert!(board.dead);
+    }
+
+    #[test]
+    fn open_positions_are_not_dead() {
+        assert!(!C4State::initial().is_drawn_dead());
+        play_random_games(200, 604, |board| {
+            // Someone who has won still has a line of their own.
+            if board.won {
+                assert!(!board.is_drawn_dead(), "\n{}", board);
+            }
+        });
+    }
+
+    /// Positions loaded with `from_str`, with the side to move and the columns that win by force.
+    const PUZZLES: [(&str, Player, &[u8]); 3] = [
+        ("......./......./......./......./OOO..../XXX....", Player::P1, &[3]),
+        // O has to take the win rather than block X's three in the bottom row.
+        ("......./......./......./O....../O....../OXXX..X", Player::P2, &[0]),
+        // Either move makes an open three on the bottom row, which can't be blocked at both ends.
+        ("......./......./......./......./..OO.../..XX...", Player::P1, &[1, 4]),
+    ];
+
+    #[test]
+    fn solves_loaded_puzzles() {
+        for &(rows, to_move, wins) in PUZZLES.iter() {
+            let board = C4State::from_str(rows).unwrap();
+            assert_eq!(board.next_player(), to_move, "{}", rows);
+            for seed in 0..4 {
+                let rng = seeded_rng(seed);
+                let mut mctree = MCTree::for_player_with_rng(board.clone(), to_move, rng);
+                mctree.set_verbosity(Verbosity::Silent);
+                mctree.search_n(20_000);
+                assert_eq!(mctree.root_value(), 1.0, "{} with seed {}", rows, seed);
+                let col = mctree.best_action().unwrap();
+                assert!(wins.contains(&col), "{} with seed {} played {}", rows, seed, col);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Column 3 is full")]
+    fn playing_into_a_full_column_panics() {
+        let mut board = C4State::initial();
+        for _ in 0..ROWS {
+            board.do_action(3);
+        }
+        assert!(!board.valid_actions(board.next).any(|col| col == 3));
+        board.do_action(3);
+    }
+
+    /// The tables derived from `ROWS` and `COLS`, as they are for the standard board.
+    #[test]
+    fn derived_tables_match_the_standard_board() {
+        assert_eq!((ROWS, COLS, CELLS), (6, 7, 42));
+        assert_eq!(ROLLOUT_WEIGHTS, [1, 2, 3, 4, 3, 2, 1]);
+        assert_eq!(CENTER_OUT, [3, 2, 4, 1, 5, 0, 6]);
+        assert_eq!((TOP_ROW, LEFT_HALF), (0b111_1111, 0b000_1111));
+        assert_eq!(SIDE_TO_MOVE, splitmix(84));
+        assert_eq!(format!("{:?}", C4State::initial().valid_actions(Player::P1)), "1111111");
+    }
+
+    #[test]
+    fn verbosity_flags() {
+        let verbosity = |args: &[&str]| {
+            Options::parse(args.iter().map(|a| a.to_string())).unwrap().verbosity
+        };
+        assert_eq!(verbosity(&[]), Verbosity::Normal);
+        assert_eq!(verbosity(&["-q"]), Verbosity::Silent);
+        assert_eq!(verbosity(&["--quiet"]), Verbosity::Silent);
+        assert_eq!(verbosity(&["-v"]), Verbosity::Debug);
+        assert_eq!(verbosity(&["-vv"]), Verbosity::Debug);
+        assert_eq!(verbosity(&["--verbose"]), Verbosity::Debug);
+    }
+
+    #[test]
+    fn column_numbers_follow_the_board_width() {
+        assert_eq!(parse_column("6", COLS), Ok(6));
+        assert_eq!(parse_column("7", COLS), Err(ParseMoveError::OutOfRange));
+        // An eight column board takes one more.
+        assert_eq!(parse_column("7", 8), Ok(7));
+        assert_eq!(parse_column("8", 8), Err(ParseMoveError::OutOfRange));
+    }
+
+    /// Runs of four consecutive bits that wrap from the end of one row to the start of the
+    /// next aren't lines on the board.
+    #[test]
+    fn lines_do_not_wrap_between_rows() {
+        let near_misses: [&[(i8, i8)]; 4] = [
+            // Bits 5 to 8: the end of the top row and the start of the next.
+            &[(0, 5), (0, 6), (1, 0), (1, 1)],
+            &[(2, 4), (2, 5), (2, 6), (3, 0)],
+            // A diagonal running off the right edge and back in on the left.
+            &[(0, 5), (1, 6), (3, 0), (4, 1)],
+            // An anti-diagonal running off the left edge and back in on the right.
+            &[(0, 1), (1, 0), (1, 6), (2, 5)],
+        ];
+        for cells in near_misses.iter() {
+            let board = with_xs(cells);
+            assert!(!board.has_won(Player::P1), "{:?}", cells);
+            assert_eq!(board.winning_line(Player::P1), None);
+            for &(row, col) in cells.iter() {
+                assert!(!board.last_move_wins(row as u8, col as u8, Player::P1), "{:?}", cells);
+            }
+        }
+    }
+}
+
+enum Command {
+    Play(u8),
+    Hint,
+    /// Save the game to the given file.
+    Save(String),
+    Quit,
+}
+
+/// Why a line of input isn't a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseMoveError {
+    NotANumber,
+    /// A number, but not that of a column on the board.
+    OutOfRange,
+    /// The player typed `quit` instead.
+    Quit,
+}
 
-fn get_column(s: &C4State) -> u8 {
+/// Parses a column number of a board `cols` wide.
+fn parse_column(token: &str, cols: u8) -> Result<u8, ParseMoveError> {
+    let token = token.trim();
+    if token == "quit" {
+        return Err(ParseMoveError::Quit);
+    }
+    // `u8::from_str` would also take a leading `+`.
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ParseMoveError::NotANumber);
+    }
+    // Digits that don't fit in a `u8` are still a number, just a far too large one.
+    match u8::from_str(token) {
+        Ok(col) if col < cols => Ok(col),
+        _ => Err(ParseMoveError::OutOfRange),
+    }
+}
+
+/// Reads a line of `input`, returning `None` at end of input or if it can't be read.
+fn read_line<I: BufRead>(input: &mut I) -> Option<String> {
     let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => None,
+        Ok(_) => Some(line),
+        Err(e) => {
+            eprintln!("Could not read input: {}", e);
+            None
+        }
+    }
+}
+
+/// Prompts until `input` gives a playable column or a command. Returns `None` at end of input.
+fn get_command<I: BufRead>(s: &C4State, input: &mut I) -> Option<Command> {
     loop {
-        println!("Enter a column: ");
-        io::stdin().read_line(&mut line).unwrap();
-        let col = match line.as_str().trim() {
-            "0" => 0,
-            "1" => 1,
-            "2" => 2,
-            "3" => 3,
-            "4" => 4,
-            "5" => 5,
-            "6" => 6,
-            _ => 7,
+        println!("Enter a column (or `hint`, `save <file>` or `quit`): ");
+        let line = read_line(input)?;
+        if line.trim() == "hint" {
+            return Some(Command::Hint);
+        }
+        if let Some(path) = line.trim().strip_prefix("save ") {
+            return Some(Command::Save(path.trim().to_string()));
+        }
+        match parse_column(&line, s.cols()) {
+            Ok(col) if s.get(0, col) == C4Cell::Blank => return Some(Command::Play(col)),
+            Ok(col) => println!("Column {} is full!", col),
+            Err(ParseMoveError::NotANumber) => println!("That's not a column number!"),
+            Err(ParseMoveError::OutOfRange) => {
+                println!("Columns are numbered 0 to {}!", s.cols() - 1)
+            }
+            Err(ParseMoveError::Quit) => return Some(Command::Quit),
+        }
+    }
+}
+
+/// Writes the game to `path` as three lines: the position it started from, in the form
+/// `--position` takes; the moves since, in the form `--moves` takes; and the current position.
+/// So `--position <first line> --moves <second line>` resumes the game.
+fn save(path: &str, start: &C4State, moves: &[u8], board: &C4State) -> io::Result<()> {
+    let transcript: Vec<String> = moves.iter().map(|col| col.to_string()).collect();
+    let (start, board) = (start.to_rows(), board.to_rows());
+    fs::write(path, format!("{}\n{}\n{}\n", start, transcript.join(","), board))
+}
+
+/// Reads a game written by `save`, returning its starting position and moves.
+fn load(path: &str) -> Result<(C4State, Vec<u8>), String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let mut lines = contents.lines();
+    let start = C4State::from_str(lines.next().unwrap_or(""))?;
+    let moves = match lines.next().map(str::trim) {
+        None | Some("") => Vec::new(),
+        Some(transcript) => parse_moves(transcript)?,
+    };
+    Ok((start, moves))
+}
+
+/// The temperature for `--random-opening`; at 1.0 moves are picked in proportion to visits.
+const OPENING_TEMPERATURE: f64 = 1.0;
+
+/// How many hopeless positions in a row it takes for the AI to resign.
+const RESIGN_MOVES: usize = 3;
+
+/// How many moves the AI expects to make in a typical game, for splitting `--game-time`.
+const EXPECTED_MOVES: usize = 21;
+
+const USAGE: &str = "Usage: c4ai [thinking_ms] [options]
+    --ai-first              let the AI make the first move
+    --position <rows>       start from a position written top row first, rows split by /
+    --moves <3,3,4,2>       start from the position after these moves
+    --iterations <n>        search a fixed number of iterations per move
+    --game-time <ms>        split a time budget across the whole game
+    --seed <u64>            seed the AI's rng, for reproducible games
+    --ponder                keep searching while waiting for your move
+    --log <file>            append a JSON line per AI move to the file
+    --random-opening <k>    vary the AI's moves during the first k plies
+    --prune-mirrors         search only one of each pair of mirrored opening moves
+    --select <value|visits> play the best valued (default) or most visited move
+    --tie-break <center|lowest|random>
+                            settle equally good moves nearest the center (default), in the
+                            lowest column, or at random
+    --handicap <p>          have the AI value a draw at p, from 0 (a loss) to 1 (a win)
+    --max-memory <MB>       prune rarely visited lines to keep the search tree under this size
+    --blunder-check         search longer when the AI's move has just collapsed in value
+    --top-moves <k>         after a quick probe, search only the k most promising moves;
+                            faster, but the probe can miss the best move
+    --resign <p>            resign once win prob stays below p (default 0.02)
+    --claim <p>             announce a win once win prob exceeds p (default 0.98)
+    --bench <iterations>    benchmark search on a fixed position and exit
+    --replay <file>         step through a saved game, comparing each move to the engine's
+    --protocol              read `position`/`go` commands and answer with `bestmove` lines
+    --analyze               print the best move for the starting position and exit
+    --quiet, -q             print only the AI's moves and the board
+    --verbose, -v, -vv      explain each AI move, or show search details with --analyze";
+
+struct Options {
+    thinking_time: usize,
+    iterations: Option<usize>,
+    seed: Option<u64>,
+    time_manager: Option<TimeManager>,
+    bench: Option<usize>,
+    ai_first: bool,
+    /// Whether the AI searches while the human thinks.
+    ponder: bool,
+    /// Where to log the AI's decisions.
+    log: Option<String>,
+    /// For this many plies from the start of the game, the AI samples its moves by visits.
+    random_opening: usize,
+    prune_mirrors: bool,
+    move_selection: MoveSelection,
+    tie_break: TieBreak,
+    /// What a draw is worth to the AI, if not half a win.
+    handicap: Option<f64>,
+    blunder_check: bool,
+    /// How many root moves the AI considers after probing, if not all of them.
+    top_moves: Option<usize>,
+    /// The most bytes the search tree may take before it is pruned.
+    memory_limit: Option<usize>,
+    /// The AI resigns after `RESIGN_MOVES` moves in a row valued below this.
+    resign_threshold: f64,
+    /// The AI announces its expected win once its value exceeds this.
+    claim_threshold: f64,
+    /// Where the game starts, before `moves`.
+    position: Option<C4State>,
+    moves: Vec<u8>,
+    analyze: bool,
+    replay: Option<String>,
+    protocol: bool,
+    verbosity: Verbosity,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            thinking_time: 3000,
+            iterations: None,
+            seed: None,
+            time_manager: None,
+            bench: None,
+            ai_first: false,
+            ponder: false,
+            log: None,
+            random_opening: 0,
+            prune_mirrors: false,
+            move_selection: MoveSelection::Value,
+            tie_break: TieBreak::FirstExpanded,
+            handicap: None,
+            blunder_check: false,
+            top_moves: None,
+            memory_limit: None,
+            resign_threshold: 0.02,
+            claim_threshold: 0.98,
+            position: None,
+            moves: Vec::new(),
+            analyze: false,
+            replay: None,
+            protocol: false,
+            verbosity: Verbosity::Normal,
         };
-        if col < 7 && s.get(0, col) == C4Cell::Blank {
-            return col;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = Some(flag_value(&arg, args.next())?),
+                "--iterations" => {
+                    let iterations = flag_value(&arg, args.next())?;
+                    if iterations == 0 {
+                        return Err("--iterations must be at least 1".to_string());
+                    }
+                    options.iterations = Some(iterations);
+                }
+                "--ai-first" => options.ai_first = true,
+                "--ponder" => options.ponder = true,
+                "--log" => options.log = Some(flag_value(&arg, args.next())?),
+                "--prune-mirrors" => options.prune_mirrors = true,
+                "--select" => options.move_selection = flag_value(&arg, args.next())?,
+                "--tie-break" => {
+                    options.tie_break = match flag_value::<String>(&arg, args.next())?.as_str() {
+                        // Moves are expanded center-out, so the first expanded is the central one.
+                        "center" => TieBreak::FirstExpanded,
+                        "lowest" => TieBreak::LowestIndex,
+                        // Seeded below, once `--seed` has been seen.
+                        "random" => TieBreak::Random(0),
+                        other => return Err(format!("Invalid value `{}` for {}", other, arg)),
+                    }
+                }
+                "--handicap" => options.handicap = Some(flag_value(&arg, args.next())?),
+                "--blunder-check" => options.blunder_check = true,
+                "--top-moves" => {
+                    let k = flag_value(&arg, args.next())?;
+                    if k == 0 {
+                        return Err("--top-moves must be at least 1".to_string());
+                    }
+                    options.top_moves = Some(k);
+                }
+                "--max-memory" => {
+                    let megabytes: usize = flag_value(&arg, args.next())?;
+                    options.memory_limit = Some(megabytes << 20);
+                }
+                "--random-opening" => options.random_opening = flag_value(&arg, args.next())?,
+                "--resign" => options.resign_threshold = flag_value(&arg, args.next())?,
+                "--claim" => options.claim_threshold = flag_value(&arg, args.next())?,
+                "--analyze" => options.analyze = true,
+                "--replay" => options.replay = Some(flag_value(&arg, args.next())?),
+                "--protocol" => options.protocol = true,
+                "--quiet" | "-q" => options.verbosity = Verbosity::Silent,
+                "--verbose" | "-v" | "-vv" => options.verbosity = Verbosity::Debug,
+                "--position" => {
+                    let rows: String = flag_value(&arg, args.next())?;
+                    options.position = Some(C4State::from_str(&rows)?);
+                }
+                "--moves" => options.moves = parse_moves(&flag_value::<String>(&arg, args.next())?)?,
+                "--bench" => options.bench = Some(flag_value(&arg, args.next())?),
+                "--game-time" => {
+                    let total = flag_value(&arg, args.next())?;
+                    options.time_manager = Some(TimeManager::new(total, EXPECTED_MOVES));
+                }
+                _ => {
+                    options.thinking_time = usize::from_str(&arg)
+                        .map_err(|_| format!("Unrecognized argument `{}`", arg))?
+                }
+            }
         }
-        println!("Invalid column!");
-        line.clear();
+        if let TieBreak::Random(ref mut seed) = options.tie_break {
+            *seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        }
+        Ok(options)
     }
 }
 
-#[allow(dead_code)]
-fn mcts(thinking_time: usize) {
-    let mut board = C4State::initial();
-    let mut mctree = MCTree::new(board.clone(), Player::P2, Player::P1);
-    mctree.search_for(thinking_time);
-    println!("{}", board);
-    loop {
-        let user_col = get_column(&board);
-        board.do_action(user_col);
-        if board.has_won(Player::P1) {
-            println!("X Won!");
-            break;
+/// Parses a comma-separated move transcript.
+fn parse_moves<T: FromStr>(transcript: &str) -> Result<Vec<T>, String> {
+    transcript
+        .split(',')
+        .enumerate()
+        .map(|(i, m)| {
+            T::from_str(m.trim()).map_err(|_| format!("Invalid move `{}` at index {}", m, i))
+        })
+        .collect()
+}
+
+/// Plays `moves` from `board`, failing on the first illegal one.
+fn replay(mut board: C4State, moves: &[u8]) -> Result<C4State, String> {
+    for (i, &m) in moves.iter().enumerate() {
+        if !board.valid_actions(board.next_player()).any(|a| a == m) {
+            return Err(format!("Illegal move {:?} at index {}", m, i));
         }
-        println!("{}", board);
-        mctree.do_action(user_col);
-        mctree.search_for(thinking_time);
-        let ai_col = mctree.choose_and_do_action();
-        board.do_action(ai_col);
-        println!("The AI played column {}", ai_col);
-        println!(
-            " it has played {} games from this position",
-            mctree.root.visits()
-        );
-        println!(
-            " and it believes it will win with p = {}",
-            mctree.root.value()
-        );
+        board.do_action(m);
+    }
+    Ok(board)
+}
+
+fn flag_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    T::from_str(&value).map_err(|_| format!("Invalid value `{}` for {}", value, flag))
+}
+
+fn search<R: Rng>(mctree: &mut MCTree<C4State, R>, options: &Options) {
+    match (options.iterations, &options.time_manager) {
+        (Some(iterations), _) => mctree.search_n(iterations),
+        (None, Some(tm)) => mctree.search_managed(tm),
+        (None, None) => mctree.search_for(options.thinking_time),
+    };
+}
+
+/// `search` for the AI's move in a game, showing what it's leaning toward as it thinks when
+/// searching for a fixed time.
+fn think<R: Rng>(mctree: &mut MCTree<C4State, R>, options: &Options) {
+    if options.iterations.is_some() || options.time_manager.is_some() ||
+        options.verbosity != Verbosity::Normal
+    {
+        return search(mctree, options);
+    }
+    // The progress line is overwritten in place, so the tree's own summary waits until it's
+    // been cleared.
+    mctree.set_verbosity(Verbosity::Silent);
+    let mut width = 0;
+    let stats = mctree.search_for_with_progress(options.thinking_time, |stats, best| {
+        if let Some(col) = best {
+            let searches = stats.iterations;
+            let line = format!("Thinking... {} searches, leaning toward column {}", searches, col);
+            print!("\r{:1$}", line, width);
+            let _ = io::stdout().flush();
+            width = line.len();
+        }
+    });
+    mctree.set_verbosity(options.verbosity);
+    if width > 0 {
+        print!("\r{:1$}\r", "", width);
+    }
+    let elapsed = stats.elapsed.as_millis();
+    println!("Did {} searches in {} milliseconds", stats.iterations, elapsed);
+}
+
+/// How far below the root's value the AI's move may be before `--blunder-check` searches on.
+const BLUNDER_CHECK_MARGIN: f64 = 0.15;
+/// The blunder check searches for this fraction of the move's usual budget.
+const BLUNDER_CHECK_SHARE: usize = 4;
+
+/// With `--blunder-check`, searches some more if the move the AI has looked at most has just been
+/// found to be much worse than the position looked, so that it doesn't walk into a refutation
+/// before an alternative has been explored.
+fn blunder_check<R: Rng>(mctree: &mut MCTree<C4State, R>, options: &Options) {
+    if !options.blunder_check || !mctree.best_is_collapsing(BLUNDER_CHECK_MARGIN) {
+        return;
+    }
+    if options.verbosity >= Verbosity::Normal {
+        println!("The AI's favorite move lost value late in the search; taking another look");
+    }
+    match options.iterations {
+        Some(iterations) => mctree.search_n(iterations / BLUNDER_CHECK_SHARE),
+        None => mctree.search_for(options.thinking_time / BLUNDER_CHECK_SHARE),
+    };
+}
+
+/// Lists every root move with the line the AI expects to follow it, then the raw statistics of
+/// the root and its children.
+fn print_reasoning<R: Rng>(mctree: &MCTree<C4State, R>) {
+    for analysis in mctree.top_moves(usize::MAX) {
+        let pv: Vec<String> = analysis.pv.iter().map(|col| col.to_string()).collect();
         println!(
-            " it has explored {} moves ahead fully, and has ventured as far as {} moves",
-            mctree.root.min_depth(),
-            mctree.root.max_depth()
+            "col {}: {} visits, win prob {:.2}, expecting {}",
+            analysis.action,
+            analysis.visits,
+            analysis.value,
+            pv.join(" ")
         );
+    }
+    mctree.root.print_1_layer();
+}
+
+/// Says how much of its search the AI kept after the human's move.
+fn print_reroot(reroot: Reroot) {
+    match reroot {
+        Reroot::Preserved { subtree_visits } => {
+            println!("The AI reused {} playouts it had made after that move", subtree_visits)
+        }
+        Reroot::Rebuilt => println!("The AI hadn't looked at that move and starts fresh"),
+    }
+}
+
+fn print_diagnostics<R: Rng>(mctree: &MCTree<C4State, R>) {
+    println!(
+        " it has played {} games from this position",
+        mctree.root_visits()
+    );
+    let (low, high) = mctree.root.value_confidence();
+    println!(
+        " and it believes it will win with p = {:.2} ± {:.2}",
+        mctree.root_value(),
+        (high - low) / 2.0
+    );
+    println!(
+        " it has explored {} moves ahead fully, and has ventured as far as {} moves",
+        mctree.min_depth(),
+        mctree.max_depth()
+    );
+}
+
+fn print_evaluations<R: Rng>(board: &C4State, mctree: &MCTree<C4State, R>) {
+    let total = mctree.root_visits() as f64;
+    let annotations: Vec<(u8, String)> = mctree
+        .root_evaluations()
+        .into_iter()
+        .map(|(col, visits, value)| {
+            let label = format!(
+                "col {}: {:.0}% visits, win prob {:.2}",
+                col,
+                100.0 * visits as f64 / total,
+                value
+            );
+            (col, label)
+        })
+        .collect();
+    println!("{}", board.render_with_annotations(&annotations));
+}
+
+/// How long the AI thinks on the human's behalf when asked for a hint.
+const HINT_TIME: usize = 1000;
+
+/// Briefly searches the current position from the human's side, with an rng seeded by `seed`,
+/// and suggests a column. With `--iterations` the hint searches that many times instead.
+fn print_hint(board: &C4State, options: &Options, seed: u64) {
+    let human = board.next_player();
+    let mut mctree = MCTree::for_player_with_rng(board.clone(), human, seeded_rng(seed));
+    mctree.set_verbosity(options.verbosity);
+    match options.iterations {
+        Some(iterations) => mctree.search_n(iterations),
+        None => mctree.search_for(HINT_TIME),
+    };
+    let col = mctree.choose_and_do_action();
+    println!(
+        "Hint: play column {} (win prob {:.2})",
+        col,
+        mctree.root_value()
+    );
+}
+
+/// Waits for the human's command while the AI keeps searching the current position.
+fn ponder<R: Rng, I: BufRead + Send>(
+    mctree: &mut MCTree<C4State, R>,
+    board: &C4State,
+    input: &mut I,
+) -> Option<Command> {
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let input = get_command(board, input);
+            stop.store(true, Ordering::Relaxed);
+            input
+        });
+        mctree.search_with_stop(&stop);
+        reader.join().unwrap()
+    })
+}
+
+/// How the end of a game is announced.
+fn result_message<A: Clone>(outcome: &Outcome<A>) -> &'static str {
+    match *outcome {
+        Outcome::P1Win => "X Won!",
+        Outcome::P2Win => "O Won!",
+        _ => "Draw",
+    }
+}
+
+fn mcts<R: Rng, I: BufRead + Send>(options: &Options, mut board: C4State, rng: R, input: &mut I) {
+    let ai = if options.ai_first { Player::P1 } else { Player::P2 };
+    let mut mctree = MCTree::for_player_with_rng(board.clone(), ai, rng);
+    mctree.set_move_selection(options.move_selection);
+    mctree.set_tie_break(options.tie_break);
+    mctree.set_memory_limit(options.memory_limit);
+    if let Some(draw) = options.handicap {
+        mctree.set_rewards(RewardScheme::default().handicapped(ai, draw));
+    }
+    mctree.set_verbosity(options.verbosity);
+    if let Some(outcome) = mctree.is_terminal() {
+        println!("{}", board);
+        println!("The game is already over: {}", result_message(&outcome));
+        return;
+    }
+    if board.next_player() != ai {
+        search(&mut mctree, options);
+    }
+    // Drives the AI's random openings and seeds hints, so `--seed` reproduces those too.
+    let mut game_rng = seeded_rng(options.seed.unwrap_or_else(|| rand::thread_rng().gen()));
+    // What `save` records: where the game began and the moves since.
+    let start = options.position.clone().unwrap_or_else(C4State::initial);
+    let mut moves = options.moves.clone();
+    let mut logger = options.log.as_ref().map(|path| {
+        MoveLogger::create(path).unwrap_or_else(|e| {
+            eprintln!("Could not open {}: {}", path, e);
+            process::exit(1)
+        })
+    });
+    let mut hopeless_moves = 0;
+    let mut claimed = false;
+    println!("{}", board);
+    loop {
+        let ai_to_move = board.next_player() == ai;
+        if ai_to_move {
+            if let Some(k) = options.top_moves {
+                mctree.restrict_root_moves(k);
+            }
+            think(&mut mctree, options);
+            blunder_check(&mut mctree, options);
+            if mctree.root_value() < options.resign_threshold {
+                hopeless_moves += 1;
+                if hopeless_moves >= RESIGN_MOVES {
+                    println!("AI resigns");
+                    break;
+                }
+            } else {
+                hopeless_moves = 0;
+            }
+            if options.verbosity >= Verbosity::Normal {
+                print_evaluations(&board, &mctree);
+            }
+            if options.verbosity >= Verbosity::Debug {
+                print_reasoning(&mctree);
+            }
+            let ai_col = if board.moves_played() < options.random_opening {
+                mctree.sample_action(OPENING_TEMPERATURE, &mut game_rng).unwrap()
+            } else {
+                mctree.best_action().unwrap()
+            };
+            if let Some(logger) = logger.as_mut() {
+                if let Err(e) = logger.log(&mctree, ai_col) {
+                    eprintln!("Could not write to the log: {}", e);
+                }
+            }
+            mctree.do_action(ai_col);
+            board.do_action(ai_col);
+            moves.push(ai_col);
+            let (row, _) = board.last_move.unwrap();
+            println!(
+                "The AI played column {}, landing in row {} from the bottom",
+                ai_col,
+                ROWS - row
+            );
+            if options.verbosity >= Verbosity::Normal {
+                print_diagnostics(&mctree);
+            }
+            println!("{}", board);
+            if !claimed && mctree.root_value() > options.claim_threshold {
+                claimed = true;
+                let pv: Vec<String> = mctree
+                    .root
+                    .principal_variation()
+                    .iter()
+                    .map(|col| col.to_string())
+                    .collect();
+                println!("The AI claims a win, expecting: {}", pv.join(" "));
+            }
+        } else {
+            let command = if options.ponder {
+                ponder(&mut mctree, &board, input)
+            } else {
+                get_command(&board, input)
+            };
+            let user_col = match command {
+                Some(Command::Play(col)) => col,
+                Some(Command::Hint) => {
+                    print_hint(&board, options, game_rng.gen());
+                    continue;
+                }
+                Some(Command::Save(path)) => {
+                    match save(&path, &start, &moves, &board) {
+                        Ok(()) => println!("Saved the game to {}", path),
+                        Err(e) => println!("Could not save to {}: {}", path, e),
+                    }
+                    continue;
+                }
+                Some(Command::Quit) => break,
+                None => {
+                    println!("End of input");
+                    break;
+                }
+            };
+            board.do_action(user_col);
+            moves.push(user_col);
+            let reroot = mctree.do_action(user_col);
+            if options.verbosity >= Verbosity::Normal {
+                print_reroot(reroot);
+            }
+        }
+        let line = board
+            .winning_line(Player::P1)
+            .or_else(|| board.winning_line(Player::P2));
+        let result = match mctree.is_terminal() {
+            Some(outcome) => result_message(&outcome),
+            None => continue,
+        };
+        if let Some(line) = line {
+            println!("{}", board.render_winning_line(&line));
+        } else if !ai_to_move {
+            println!("{}", board);
+        }
+        println!("{}", result);
+        break;
+    }
+}
+
+/// The columns played to reach the benchmark position.
+const BENCH_MOVES: [u8; 8] = [3, 3, 2, 4, 4, 2, 3, 5];
+/// How many times the benchmark search is repeated; the reported numbers are averages.
+const BENCH_RUNS: usize = 5;
+
+/// Searches a fixed mid-game position for `iterations` iterations, several times over, and
+/// prints the average throughput and tree size as plain numbers.
+fn bench<R: Rng>(iterations: usize, mut rng: R) {
+    let mut board = C4State::initial();
+    for &col in BENCH_MOVES.iter() {
+        board.do_action(col);
+    }
+    let mut seconds = 0.0;
+    let mut tree_size = 0;
+    for _ in 0..BENCH_RUNS {
+        let to_move = board.next_player();
+        let mut mctree = MCTree::for_player_with_rng(board.clone(), to_move, &mut rng);
+        // Only the numbers below are printed, so that runs can be diffed.
+        mctree.set_verbosity(Verbosity::Silent);
+        let start = Instant::now();
+        mctree.search_n(iterations);
+        seconds += start.elapsed().as_secs_f64();
+        tree_size += mctree.root.size();
+    }
+    println!("iterations {}", iterations);
+    println!("playouts_per_sec {:.0}", (iterations * BENCH_RUNS) as f64 / seconds);
+    println!("avg_tree_size {}", tree_size / BENCH_RUNS);
+}
+
+/// Searches `board` for the side to move and prints a single machine-readable line, e.g.
+/// `bestmove 3 value 0.62 visits 41231`.
+fn analyze<R: Rng>(options: &Options, board: C4State, rng: R) {
+    let to_move = board.next_player();
+    let mut mctree = MCTree::for_player_with_rng(board.clone(), to_move, rng);
+    // Only the result line is printed unless searching is to be explained.
+    let debug = options.verbosity >= Verbosity::Debug;
+    mctree.set_verbosity(if debug { Verbosity::Debug } else { Verbosity::Silent });
+    mctree.set_move_selection(options.move_selection);
+    mctree.set_tie_break(options.tie_break);
+    mctree.set_memory_limit(options.memory_limit);
+    if let Some(outcome) = mctree.is_terminal() {
+        println!("The game is already over: {}", result_message(&outcome));
+        return;
+    }
+    search(&mut mctree, options);
+    if debug {
+        print_evaluations(&board, &mctree);
+    }
+    let visits = mctree.root_visits();
+    let col = mctree.choose_and_do_action();
+    println!(
+        "bestmove {} value {:.2} visits {}",
+        col,
+        mctree.root_value(),
+        visits
+    );
+}
+
+/// How long the engine searches each position of a game under review, unless `--iterations` is
+/// given.
+const REVIEW_TIME: usize = 500;
+
+/// A played move whose win prob is this far below the engine's choice is flagged as a blunder.
+const BLUNDER_MARGIN: f64 = 0.2;
+
+/// Steps through the game saved at `options.replay`, waiting for Enter after each move and
+/// comparing it to the engine's choice.
+fn review<R: Rng, I: BufRead>(options: &Options, mut rng: R, input: &mut I) {
+    let (mut board, moves) = load(options.replay.as_ref().unwrap()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1)
+    });
+    println!("{}", board);
+    for (i, &col) in moves.iter().enumerate() {
+        let to_move = board.next_player();
+        if !board.valid_actions(to_move).any(|a| a == col) {
+            eprintln!("Illegal move {} at index {}", col, i);
+            process::exit(1);
+        }
+        let mut mctree = MCTree::for_player_with_rng(board.clone(), to_move, &mut rng);
+        mctree.set_verbose(false);
+        match options.iterations {
+            Some(iterations) => mctree.search_n(iterations),
+            None => mctree.search_for(REVIEW_TIME),
+        };
+        let evaluations = mctree.root_evaluations();
+        let value_of = |col| evaluations.iter().find(|e| e.0 == col).map(|e| e.2);
+        let best = mctree.best_action().unwrap();
+        let mover = if to_move == Player::P1 { "X" } else { "O" };
+        println!("Move {}: {} plays column {}", i + 1, mover, col);
+        if col == best {
+            println!(" the engine agrees");
+        } else {
+            let best_value = value_of(best).unwrap();
+            match value_of(col) {
+                Some(value) if best_value - value > BLUNDER_MARGIN => println!(
+                    " blunder? the engine prefers column {} (win prob {:.2} vs {:.2})",
+                    best, best_value, value
+                ),
+                Some(value) => println!(
+                    " the engine prefers column {} (win prob {:.2} vs {:.2})",
+                    best, best_value, value
+                ),
+                None => println!(" the engine prefers column {} and never tried this one", best),
+            }
+        }
+        board.do_action(col);
         println!("{}", board);
-        if board.has_won(Player::P2) {
-            println!("O Won!");
-            break;
+        if i + 1 < moves.len() {
+            println!("Press Enter for the next move");
+            if read_line(input).is_none() {
+                break;
+            }
         }
-        if board.valid_actions(Player::P1).len() == 0 {
-            println!("Draw");
-            break;
+    }
+}
+
+/// The columns that lead from `from` to `to`, if `to` is `from` with more pieces played.
+fn moves_between(from: &C4State, to: &C4State) -> Option<Vec<u8>> {
+    if from.xs & !to.xs != 0 || from.os & !to.os != 0 {
+        return None;
+    }
+    let mut board = from.clone();
+    let mut moves = Vec::new();
+    while (board.xs, board.os) != (to.xs, to.os) {
+        let target = match board.next_player() {
+            Player::P1 => to.xs & !board.xs,
+            Player::P2 => to.os & !board.os,
+        };
+        // Any of the mover's new pieces that can be dropped now will do.
+        let col = board
+            .valid_actions(board.next_player())
+            .find(|&col| (0..ROWS).rev().any(|row| {
+                board.get(row, col) == C4Cell::Blank && (target >> (row * COLS + col)) & 1 == 1 &&
+                    (row == ROWS - 1 || board.get(row + 1, col) != C4Cell::Blank)
+            }))?;
+        board.do_action(col);
+        moves.push(col);
+    }
+    Some(moves)
+}
+
+/// Answers commands from a GUI or match manager, one per line:
+///
+/// - `position start` or `position <rows>`, as for `--position`, sets the position.
+/// - `go` searches it as configured on the command line, or `go <ms>` for that long, and
+///   answers `bestmove <col> value <p> visits <n>`.
+/// - `quit` exits.
+///
+/// The search tree carries over to later positions reached from the current one.
+fn protocol<R: Rng, I: BufRead>(options: &Options, mut board: C4State, mut rng: R, input: &mut I) {
+    let new_tree = |board: &C4State, rng: &mut R| {
+        let seed = rng.gen();
+        let mut mctree =
+            MCTree::for_player_with_rng(board.clone(), board.next_player(), seeded_rng(seed));
+        mctree.set_verbosity(Verbosity::Silent);
+        mctree.set_move_selection(options.move_selection);
+        mctree.set_tie_break(options.tie_break);
+        mctree.set_memory_limit(options.memory_limit);
+        mctree
+    };
+    let mut mctree = new_tree(&board, &mut rng);
+    while let Some(line) = read_line(input) {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("position"), Some(rows)) => {
+                let position = if rows == "start" { Ok(C4State::initial()) } else { rows.parse() };
+                let mut position = match position {
+                    Ok(position) => position,
+                    Err(e) => {
+                        println!("error {}", e);
+                        continue;
+                    }
+                };
+                // Before any new tree copies it.
+                position.prune_mirrors = options.prune_mirrors;
+                // The tree plays for whoever is to move, so it carries over when they are again.
+                match moves_between(&board, &position) {
+                    Some(ref moves) if moves.len() % 2 == 0 => {
+                        for &col in moves {
+                            mctree.do_action(col);
+                        }
+                    }
+                    _ => mctree = new_tree(&position, &mut rng),
+                }
+                board = position;
+            }
+            (Some("go"), budget) => {
+                if mctree.is_terminal().is_some() {
+                    println!("error the game is over");
+                    continue;
+                }
+                match budget.map(usize::from_str) {
+                    Some(Ok(milliseconds)) => {
+                        mctree.search_for(milliseconds);
+                    }
+                    Some(Err(_)) => {
+                        println!("error invalid search time");
+                        continue;
+                    }
+                    None => search(&mut mctree, options),
+                }
+                // Too short a search may not have expanded a single move.
+                if mctree.best_action().is_none() {
+                    mctree.expand_root_fully();
+                }
+                let col = match mctree.best_action() {
+                    Some(col) => col,
+                    None => {
+                        println!("error no move to play");
+                        continue;
+                    }
+                };
+                // Report the move without playing it; the next `position` says what happened.
+                let best = mctree.root.children().iter().find(|c| c.action() == Some(col));
+                println!(
+                    "bestmove {} value {:.2} visits {}",
+                    col,
+                    best.unwrap().value(),
+                    mctree.root_visits()
+                );
+            }
+            (Some("quit"), _) => break,
+            (None, _) => {}
+            (Some(command), _) => println!("error unknown command `{}`", command),
         }
     }
 }
 
+fn run<R: Rng, I: BufRead + Send>(options: &Options, board: C4State, rng: R, input: &mut I) {
+    match options.bench {
+        Some(iterations) => bench(iterations, rng),
+        None if options.analyze => analyze(options, board, rng),
+        None if options.replay.is_some() => review(options, rng, input),
+        None if options.protocol => protocol(options, board, rng, input),
+        None => mcts(options, board, rng, input),
+    }
+}
+
 fn main() {
-    let thinking_time = env::args()
-        .nth(1)
-        .and_then(|a| usize::from_str(&a).ok())
-        .unwrap_or(3000);
-    mcts(thinking_time)
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("{}", USAGE);
+        process::exit(1)
+    });
+    let start = options.position.clone().unwrap_or_else(C4State::initial);
+    let mut board = replay(start, &options.moves).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1)
+    });
+    board.prune_mirrors = options.prune_mirrors;
+    // Not `stdin().lock()`, since pondering reads input on another thread.
+    let mut input = io::BufReader::new(io::stdin());
+    match options.seed {
+        Some(seed) => run(&options, board, seeded_rng(seed), &mut input),
+        None => run(&options, board, rand::thread_rng(), &mut input),
+    }
 }
@@ -0,0 +1,34 @@
+//! Tests that drive the engine through `--protocol`, as a GUI or match manager would.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The engine's replies to `commands`, one per line.
+fn run_protocol(commands: &str) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_c4ai"))
+        .args(["--protocol", "--seed", "607"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run c4ai");
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "c4ai exited with {}", output.status);
+    String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect()
+}
+
+/// A search too short to expand any move still answers with one, and the engine keeps going.
+#[test]
+fn go_0_still_plays_a_move() {
+    let replies = run_protocol("position start\ngo 0\ngo 0\nquit\n");
+    assert_eq!(replies.len(), 2, "{:?}", replies);
+    for reply in replies {
+        assert!(reply.starts_with("bestmove "), "{}", reply);
+    }
+}
+
+#[test]
+fn go_in_a_finished_game_is_an_error() {
+    let replies = run_protocol("position ......./......./X....../X.....O/X.....O/X.....O\ngo 0\n");
+    assert_eq!(replies, ["error the game is over"]);
+}
@@ -0,0 +1,36 @@
+//! Regression tests that run the engine on fixed positions through `--analyze`.
+
+use std::process::Command;
+
+/// The column `--analyze` picks for `position` after `iterations` searches seeded with `seed`.
+fn best_move(position: &str, iterations: usize, seed: u64) -> u8 {
+    let output = Command::new(env!("CARGO_BIN_EXE_c4ai"))
+        .args(["--position", position, "--analyze"])
+        .args(["--iterations", &iterations.to_string(), "--seed", &seed.to_string()])
+        .output()
+        .expect("failed to run c4ai");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut words = stdout.split_whitespace();
+    assert_eq!(words.next(), Some("bestmove"), "unexpected output: {}", stdout);
+    words.next().and_then(|col| col.parse().ok()).expect("missing column")
+}
+
+/// X wins at once in column 0 and must not play elsewhere, even to block O's win in column 6.
+/// Once the win was found, the engine used to play any move whose single playout had been won,
+/// as it ranked them equal to the proven win.
+#[test]
+fn takes_a_win_in_one() {
+    let position = "......./......./......./X.....O/X.....O/X.....O";
+    for seed in 0..8 {
+        assert_eq!(best_move(position, 100, seed), 0, "seed {}", seed);
+    }
+}
+
+/// O, to move, must block X's three in the bottom row rather than play in the center.
+#[test]
+fn blocks_a_win_in_one() {
+    let position = "......./......./......./......./......./OXXX..O";
+    for seed in 0..8 {
+        assert_eq!(best_move(position, 1000, seed), 4, "seed {}", seed);
+    }
+}
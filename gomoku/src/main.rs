@@ -0,0 +1,423 @@
+extern crate mcts;
+extern crate rand;
+
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::env;
+use std::process;
+use mcts::*;
+use rand::Rng;
+
+use std::str::FromStr;
+
+/// The board is `SIZE` by `SIZE`, and `STREAK` in a row wins.
+const SIZE: u8 = 15;
+const STREAK: usize = 5;
+const CELLS: usize = SIZE as usize * SIZE as usize;
+
+/// One bit per cell, numbered `row * SIZE + col`.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Bitset([u64; 4]);
+
+impl Bitset {
+    fn get(&self, i: usize) -> bool {
+        (self.0[i / 64] >> (i % 64)) & 1 == 1
+    }
+    fn set(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+    fn clear(&mut self, i: usize) {
+        self.0[i / 64] &= !(1 << (i % 64));
+    }
+    fn count(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    /// The cells in neither set.
+    fn empty_cells(&self, other: &Bitset) -> Bitset {
+        let mut empty = Bitset([0; 4]);
+        for w in 0..4 {
+            empty.0[w] = !(self.0[w] | other.0[w]);
+        }
+        // Bits past the last cell don't belong to the board.
+        empty.0[3] &= (1 << (CELLS - 192)) - 1;
+        empty
+    }
+}
+
+#[derive(Clone)]
+struct GomokuState {
+    xs: Bitset,
+    os: Bitset,
+    next: Player,
+    /// Whether either player has five in a row, cached by `do_action`.
+    won: bool,
+}
+
+fn index(row: u8, col: u8) -> usize {
+    row as usize * SIZE as usize + col as usize
+}
+
+impl fmt::Display for GomokuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "  ")?;
+        for c in 0..SIZE {
+            write!(f, " {}", (b'a' + c) as char)?;
+        }
+        for r in 0..SIZE {
+            writeln!(f)?;
+            write!(f, "{:2}", r + 1)?;
+            for c in 0..SIZE {
+                let cell = if self.xs.get(index(r, c)) {
+                    "X"
+                } else if self.os.get(index(r, c)) {
+                    "O"
+                } else {
+                    "."
+                };
+                write!(f, " {}", cell)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GomokuState {
+    fn stones(&self, player: Player) -> &Bitset {
+        match player {
+            Player::P1 => &self.xs,
+            Player::P2 => &self.os,
+        }
+    }
+    fn is_empty(&self, row: u8, col: u8) -> bool {
+        !self.xs.get(index(row, col)) && !self.os.get(index(row, col))
+    }
+    /// Whether the stone `player` has at `(row, col)` is part of five in a row. Any line made by
+    /// the last move passes through its cell, so this is all `do_action` needs to check.
+    fn last_move_wins(&self, row: u8, col: u8, player: Player) -> bool {
+        let stones = self.stones(player);
+        let size = SIZE as i16;
+        let owns = |r: i16, c: i16| {
+            (0..size).contains(&r) && (0..size).contains(&c) &&
+                stones.get((r * size + c) as usize)
+        };
+        [(0, 1), (1, 0), (1, 1), (1, -1)].iter().any(|&(dr, dc)| {
+            let run = |sign: i16| {
+                (1..STREAK as i16)
+                    .take_while(|k| owns(row as i16 + sign * k * dr, col as i16 + sign * k * dc))
+                    .count()
+            };
+            run(1) + run(-1) + 1 >= STREAK
+        })
+    }
+}
+
+impl State for GomokuState {
+    type Action = (u8, u8);
+    type Actions = GomokuActions;
+
+    fn initial() -> Self {
+        GomokuState {
+            xs: Bitset::default(),
+            os: Bitset::default(),
+            next: Player::P1,
+            won: false,
+        }
+    }
+
+    fn next_player(&self) -> Player {
+        self.next
+    }
+
+    fn do_action(&mut self, (row, col): Self::Action) -> Outcome<Self::Actions> {
+        let player = self.next;
+        match player {
+            Player::P1 => self.xs.set(index(row, col)),
+            Player::P2 => self.os.set(index(row, col)),
+        }
+        self.next = player.other();
+        self.won = self.last_move_wins(row, col, player);
+        if self.won {
+            Outcome::from_player(player)
+        } else if self.xs.count() + self.os.count() == CELLS {
+            Outcome::Draw
+        } else {
+            Outcome::Actions(self.valid_actions(self.next))
+        }
+    }
+
+    fn valid_actions(&self, _: Player) -> Self::Actions {
+        let cells = if self.won {
+            Bitset::default()
+        } else {
+            self.xs.empty_cells(&self.os)
+        };
+        GomokuActions { cells }
+    }
+
+    fn has_won(&self, player: Player) -> bool {
+        (0..SIZE).any(|r| {
+            (0..SIZE).any(|c| {
+                self.stones(player).get(index(r, c)) && self.last_move_wins(r, c, player)
+            })
+        })
+    }
+
+    /// Every move fills a cell, so no game outlasts the board.
+    fn max_playout_moves() -> usize {
+        CELLS
+    }
+}
+
+/// The empty cells, in index order.
+#[derive(Clone, Default)]
+struct GomokuActions {
+    cells: Bitset,
+}
+
+impl fmt::Debug for GomokuActions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} empty cells", self.cells.count())
+    }
+}
+
+impl Iterator for GomokuActions {
+    type Item = (u8, u8);
+    fn next(&mut self) -> Option<Self::Item> {
+        let w = self.cells.0.iter().position(|&w| w != 0)?;
+        let i = w * 64 + self.cells.0[w].trailing_zeros() as usize;
+        self.cells.clear(i);
+        Some(((i / SIZE as usize) as u8, (i % SIZE as usize) as u8))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let ones = self.cells.count();
+        (ones, Some(ones))
+    }
+}
+
+impl ExactSizeIterator for GomokuActions {}
+
+/// Why a line of input isn't a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseMoveError {
+    /// Not a letter followed by a number.
+    NotACell,
+    /// A cell, but not one on the board.
+    OutOfRange,
+    /// The player typed `quit` instead.
+    Quit,
+}
+
+/// Parses a cell like `h8`: a column letter followed by a row number.
+fn parse_cell(token: &str) -> Result<(u8, u8), ParseMoveError> {
+    let token = token.trim();
+    if token == "quit" {
+        return Err(ParseMoveError::Quit);
+    }
+    let (letter, number) = match token.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => (c.to_ascii_lowercase() as u8, &token[1..]),
+        _ => return Err(ParseMoveError::NotACell),
+    };
+    // `u8::from_str` would also take a leading `+`.
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ParseMoveError::NotACell);
+    }
+    let col = letter - b'a';
+    match u8::from_str(number) {
+        Ok(row @ 1..) if row <= SIZE && col < SIZE => Ok((row - 1, col)),
+        _ => Err(ParseMoveError::OutOfRange),
+    }
+}
+
+/// Prompts until `input` gives an empty cell. Returns `None` at end of input or once the player
+/// quits.
+fn get_move<I: BufRead>(s: &GomokuState, input: &mut I) -> Option<(u8, u8)> {
+    let mut line = String::new();
+    loop {
+        println!("Enter a cell (e.g. h8, or `quit`): ");
+        line.clear();
+        match input.read_line(&mut line) {
+            Ok(0) => {
+                println!("End of input");
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Could not read input: {}", e);
+                return None;
+            }
+        }
+        match parse_cell(&line) {
+            Ok((row, col)) if s.is_empty(row, col) => return Some((row, col)),
+            Ok(_) => println!("That cell is taken!"),
+            Err(ParseMoveError::NotACell) => println!("That's not a cell!"),
+            Err(ParseMoveError::OutOfRange) => {
+                let last = format_cell((SIZE - 1, SIZE - 1));
+                println!("Cells go from a1 to {}!", last)
+            }
+            Err(ParseMoveError::Quit) => return None,
+        }
+    }
+}
+
+fn format_cell((row, col): (u8, u8)) -> String {
+    format!("{}{}", (b'a' + col) as char, row + 1)
+}
+
+/// How many hopeless positions in a row it takes for the AI to resign.
+const RESIGN_MOVES: usize = 3;
+
+const USAGE: &str = "Usage: gomoku [thinking_ms] [options]
+    --ai-first              let the AI make the first move
+    --iterations <n>        search a fixed number of iterations per move
+    --seed <u64>            seed the AI's rng, for reproducible games
+    --select <value|visits> play the best valued (default) or most visited move
+    --resign <p>            resign once win prob stays below p (default 0.02)
+    --claim <p>             announce a win once win prob exceeds p (default 0.98)
+    --quiet, -q             print only the AI's moves and the board
+    --verbose, -v, -vv      also show the shape of the search tree";
+
+struct Options {
+    thinking_time: usize,
+    iterations: Option<usize>,
+    seed: Option<u64>,
+    ai_first: bool,
+    move_selection: MoveSelection,
+    verbosity: Verbosity,
+    /// The AI resigns after `RESIGN_MOVES` moves in a row valued below this.
+    resign_threshold: f64,
+    /// The AI announces its expected win once its value exceeds this.
+    claim_threshold: f64,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            thinking_time: 3000,
+            iterations: None,
+            seed: None,
+            ai_first: false,
+            move_selection: MoveSelection::Value,
+            verbosity: Verbosity::Normal,
+            resign_threshold: 0.02,
+            claim_threshold: 0.98,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = Some(flag_value(&arg, args.next())?),
+                "--iterations" => {
+                    let iterations = flag_value(&arg, args.next())?;
+                    if iterations == 0 {
+                        return Err("--iterations must be at least 1".to_string());
+                    }
+                    options.iterations = Some(iterations);
+                }
+                "--ai-first" => options.ai_first = true,
+                "--quiet" | "-q" => options.verbosity = Verbosity::Silent,
+                "--verbose" | "-v" | "-vv" => options.verbosity = Verbosity::Debug,
+                "--select" => options.move_selection = flag_value(&arg, args.next())?,
+                "--resign" => options.resign_threshold = flag_value(&arg, args.next())?,
+                "--claim" => options.claim_threshold = flag_value(&arg, args.next())?,
+                _ => {
+                    options.thinking_time = usize::from_str(&arg)
+                        .map_err(|_| format!("Unrecognized argument `{}`", arg))?
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn flag_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    T::from_str(&value).map_err(|_| format!("Invalid value `{}` for {}", value, flag))
+}
+
+fn search<R: Rng>(mctree: &mut MCTree<GomokuState, R>, options: &Options) {
+    match options.iterations {
+        Some(iterations) => mctree.search_n(iterations),
+        None => mctree.search_for(options.thinking_time),
+    };
+}
+
+fn mcts<R: Rng, I: BufRead>(options: &Options, rng: R, input: &mut I) {
+    let mut board = GomokuState::initial();
+    let ai = if options.ai_first { Player::P1 } else { Player::P2 };
+    let mut mctree = MCTree::for_player_with_rng(board.clone(), ai, rng);
+    mctree.set_move_selection(options.move_selection);
+    mctree.set_verbosity(options.verbosity);
+    if board.next_player() != ai {
+        search(&mut mctree, options);
+    }
+    let mut hopeless_moves = 0;
+    let mut claimed = false;
+    println!("{}", board);
+    loop {
+        let ai_to_move = board.next_player() == ai;
+        if ai_to_move {
+            search(&mut mctree, options);
+            if mctree.root_value() < options.resign_threshold {
+                hopeless_moves += 1;
+                if hopeless_moves >= RESIGN_MOVES {
+                    println!("AI resigns");
+                    break;
+                }
+            } else {
+                hopeless_moves = 0;
+            }
+            let ai_move = mctree.choose_and_do_action();
+            board.do_action(ai_move);
+            println!("The AI played {}", format_cell(ai_move));
+            if options.verbosity >= Verbosity::Normal {
+                println!(
+                    " it has played {} games from this position",
+                    mctree.root_visits()
+                );
+                println!(" and it believes it will win with p = {:.2}", mctree.root_value());
+            }
+            println!("{}", board);
+            if !claimed && mctree.root_value() > options.claim_threshold {
+                claimed = true;
+                let pv: Vec<String> = mctree
+                    .root
+                    .principal_variation()
+                    .iter()
+                    .map(|&cell| format_cell(cell))
+                    .collect();
+                println!("The AI claims a win, expecting: {}", pv.join(" "));
+            }
+        } else {
+            let user_move = match get_move(&board, input) {
+                Some(m) => m,
+                None => break,
+            };
+            board.do_action(user_move);
+            mctree.do_action(user_move);
+        }
+        let result = match mctree.is_terminal() {
+            Some(Outcome::P1Win) => "X Won!",
+            Some(Outcome::P2Win) => "O Won!",
+            Some(_) => "Draw",
+            None => continue,
+        };
+        if !ai_to_move {
+            println!("{}", board);
+        }
+        println!("{}", result);
+        break;
+    }
+}
+
+fn main() {
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("{}", USAGE);
+        process::exit(1)
+    });
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    match options.seed {
+        Some(seed) => mcts(&options, seeded_rng(seed), &mut input),
+        None => mcts(&options, rand::thread_rng(), &mut input),
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use {MCTree, Outcome, Player, State};
+
+/// The object-safe part of playing a game against the engine. `State` itself can't be a trait
+/// object, but `Box<dyn DynGame>` can, so one loop can drive whichever game was chosen at
+/// runtime, with moves read and written as text.
+pub trait DynGame {
+    fn render(&self) -> String;
+    fn to_move(&self) -> Player;
+    /// Whether it's the AI's turn.
+    fn ai_to_move(&self) -> bool;
+    /// Plays a move written the way the game's action parses, e.g. `3` in Connect Four.
+    fn play(&mut self, input: &str) -> Result<(), String>;
+    /// Searches for `milliseconds` and plays the AI's choice, which is returned written out.
+    /// Panics if it isn't the AI's turn.
+    fn ai_move(&mut self, milliseconds: usize) -> String;
+    /// The result, once the game is over.
+    fn outcome(&self) -> Option<Outcome<()>>;
+}
+
+/// A game of any `State` against the engine, usable as a `DynGame`.
+pub struct Session<S: State, R: Rng> {
+    state: S,
+    tree: MCTree<S, R>,
+    ai: Player,
+}
+
+impl<S: State, R: Rng> Session<S, R> {
+    /// Starts from `state`, with the engine playing as `ai`.
+    pub fn new(state: S, ai: Player, rng: R) -> Self {
+        let mut tree = MCTree::for_player_with_rng(state.clone(), ai, rng);
+        tree.set_verbose(false);
+        Session { state, tree, ai }
+    }
+}
+
+impl<S: State, R: Rng> DynGame for Session<S, R>
+where
+    S::Action: FromStr + fmt::Display,
+{
+    fn render(&self) -> String {
+        self.state.render()
+    }
+    fn to_move(&self) -> Player {
+        self.state.next_player()
+    }
+    fn ai_to_move(&self) -> bool {
+        self.state.next_player() == self.ai
+    }
+    fn play(&mut self, input: &str) -> Result<(), String> {
+        let action = S::Action::from_str(input.trim())
+            .map_err(|_| format!("Can't read a move from `{}`", input.trim()))?;
+        let player = self.state.next_player();
+        if !self.state.valid_actions(player).any(|a| a == action) {
+            return Err(format!("{} isn't a legal move", action));
+        }
+        self.state.do_action(action);
+        self.tree.do_action(action);
+        Ok(())
+    }
+    fn ai_move(&mut self, milliseconds: usize) -> String {
+        self.tree.search_for(milliseconds);
+        // Too short a search may not have expanded a single move.
+        if self.tree.best_action().is_none() {
+            self.tree.expand_root_fully();
+        }
+        let action = self.tree.choose_and_do_action();
+        self.state.do_action(action);
+        action.to_string()
+    }
+    fn outcome(&self) -> Option<Outcome<()>> {
+        match self.state.outcome() {
+            Outcome::P1Win => Some(Outcome::P1Win),
+            Outcome::P2Win => Some(Outcome::P2Win),
+            Outcome::Draw => Some(Outcome::Draw),
+            Outcome::Actions(_) => None,
+        }
+    }
+}
@@ -1,20 +1,58 @@
 extern crate rand;
 
+mod dyn_game;
+mod move_logger;
+mod oracle;
+mod perft;
+mod self_play;
+mod solve;
+mod time_manager;
+
 use rand::distributions::{IndependentSample, Range};
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
 use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
+use std::str::FromStr;
+use std::sync::atomic::{self, AtomicBool};
 use std::time;
 use rand::Rng;
 
-#[derive(Debug, PartialEq)]
+pub use dyn_game::{DynGame, Session};
+pub use move_logger::MoveLogger;
+pub use oracle::{oracle_agreement, Oracle, OracleReport};
+pub use perft::perft;
+pub use self_play::{
+    play_match, play_match_from, play_series, self_play, strength_ladder, Record, SeriesReport,
+};
+pub use solve::solve;
+pub use time_manager::TimeManager;
+
+/// The number of players in a game. Per-player values are indexed by `Player::index`.
+pub const NUM_PLAYERS: usize = 2;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Node<S: State> {
     action: Option<S::Action>,
     visits: usize,
-    value: f64,
+    /// The average reward of each player over this node's playouts.
+    values: [f64; NUM_PLAYERS],
+    /// The average squared reward of each player, for the variance used by
+    /// `Selection::Ucb1Tuned`.
+    squares: [f64; NUM_PLAYERS],
     untried_actions: S::Actions,
     children: Vec<Node<S>>,
     just_acted: Player,
+    /// The player whose value `value` reports.
+    perspective: Player,
+    /// The game-theoretic values of this node, once they have been proven.
+    solved: Option<[f64; NUM_PLAYERS]>,
+    /// The policy's probability of `action` being played, used by `Selection::Puct`.
+    prior: f64,
+    /// Whether `restrict_root_moves` dropped some of this node's moves, so that proving the
+    /// rest doesn't prove the node.
+    restricted: bool,
 }
 
 fn f64_cmp(a: f64, b: f64) -> Ordering {
@@ -22,78 +60,260 @@ fn f64_cmp(a: f64, b: f64) -> Ordering {
 }
 
 impl<S: State> Node<S> {
-    /// Returns the value of the result
-    fn select<R: Rng>(&mut self, mut state: S, rng: &mut R, player: Player) -> f64 {
+    /// Returns the values of the result
+    fn select<R: Rng>(
+        &mut self,
+        mut state: S,
+        rng: &mut R,
+        config: &Config,
+        tally: &mut Tally,
+        evaluator: Option<&dyn Evaluator<S>>,
+    ) -> [f64; NUM_PLAYERS] {
         self.action.map(|a| state.do_action(a));
-        match self.untried_actions.next() {
+        if let Some(values) = self.solved {
+            self.visits += 1;
+            return values;
+        }
+        let mover = self.just_acted.other();
+        // Widening never blocks a node whose expanded moves are all proven, since there would
+        // be nothing left to select.
+        let widening_allows = config
+            .widening
+            .is_none_or(|w| self.children.len() < w.max_children(self.visits)) ||
+            self.children.iter().all(|c| c.solved.is_some());
+        let has_room = config.node_limit.is_none_or(|limit| tally.nodes < limit);
+        let next_action = if widening_allows && has_room {
+            self.untried_actions.next()
+        } else {
+            None
+        };
+        let val = match next_action {
             None => {
-                if self.children.len() == 0 {
-                    self.visits += 1;
-                    self.value
-                } else {
-                    let max = player != self.just_acted;
-                    let val = self.choose_child(max).unwrap().select(state, rng, player);
-                    self.value = (self.value * self.visits as f64 + val) /
-                        (self.visits as f64 + 1.0);
-                    self.visits += 1;
-                    val
-                }
+                let val = match self.choose_child(mover, config, &state) {
+                    Some(child) => child.select(state, rng, config, tally, evaluator),
+                    // Only reachable when the node limit stops expansion, or at a restricted
+                    // root whose remaining moves are all proven.
+                    None => {
+                        let outcome = state.outcome();
+                        let (values, moves) = leaf_values(state, outcome, rng, config, evaluator);
+                        tally.playout_moves = moves;
+                        values
+                    }
+                };
+                self.record(val);
+                val
             }
             Some(action) => {
+                tally.nodes += 1;
+                let prior = match config.selection {
+                    Selection::Ucb1 | Selection::Ucb1Tuned => 1.0,
+                    Selection::Puct { .. } => state
+                        .action_priors()
+                        .into_iter()
+                        .find(|&(a, _)| a == action)
+                        .map_or(0.0, |(_, p)| p),
+                };
                 let outcome = state.do_action(action);
-                self.children.push(Node::new(
-                    Some(action),
-                    self.just_acted.other(),
-                    state,
-                    outcome,
-                    player,
-                    rng,
-                ));
-                let val = self.children.last().unwrap().value;
-                self.value = (self.value * self.visits as f64 + val) / (self.visits as f64 + 1.0);
-                self.visits += 1;
+                let mut child = Node::new(Some(action), mover, &state, &outcome, self.perspective);
+                child.prior = prior;
+                tally.playout_moves = child.evaluate(state, outcome, rng, config, evaluator);
+                let val = child.values;
+                let capacity = self.children.capacity();
+                self.children.push(child);
+                tally.slots += self.children.capacity() - capacity;
+                self.record(val);
                 val
             }
+        };
+        self.update_solved(mover, &config.rewards);
+        val
+    }
+    /// Folds one more playout result into the running averages.
+    fn record(&mut self, val: [f64; NUM_PLAYERS]) {
+        let visits = self.visits as f64;
+        for (value, reward) in self.values.iter_mut().zip(val.iter()) {
+            *value = (*value * visits + reward) / (visits + 1.0);
+        }
+        for (square, reward) in self.squares.iter_mut().zip(val.iter()) {
+            *square = (*square * visits + reward * reward) / (visits + 1.0);
+        }
+        self.visits += 1;
+    }
+    /// Marks this node as solved if a child is a proven win for `mover`, or if every move has
+    /// been expanded and proven, in which case `mover` takes the best of them. A `restricted`
+    /// node can only be solved by a win, since its dropped moves might have done better.
+    fn update_solved(&mut self, mover: Player, rewards: &RewardScheme) {
+        let i = mover.index();
+        let mut all_solved = self.untried_actions.len() == 0 && !self.restricted;
+        let mut best: Option<[f64; NUM_PLAYERS]> = None;
+        for child in &self.children {
+            match child.solved {
+                Some(values) if values[i] == rewards.win => {
+                    best = Some(values);
+                    all_solved = true;
+                    break;
+                }
+                Some(values) => {
+                    if best.is_none_or(|b| values[i] > b[i]) {
+                        best = Some(values);
+                    }
+                }
+                None => all_solved = false,
+            }
+        }
+        if let (true, Some(values)) = (all_solved, best) {
+            self.solved = Some(values);
+            self.values = values;
         }
     }
-    fn choose_child(&mut self, max: bool) -> Option<&mut Node<S>> {
-        let visits: usize = self.visits;
-        let weight = |c: &Node<S>| if max { c.value } else { 1.0 - c.value } +
-            ((visits as f64 * 2.0).ln() / c.visits as f64).sqrt();
-        self.children.iter_mut().max_by(
-            |a, b| f64_cmp(weight(a), weight(b)),
-        )
+    /// Chooses the child with the best upper confidence bound on `mover`'s value. Proven
+    /// children are never chosen: a proven win for `mover` would have solved this node, and
+    /// proven losses and draws have nothing left to explore. Ties go to the more visited child,
+    /// then as `config.tie_break` says.
+    ///
+    /// Every child has been visited at least once, by the playout that created it; trying each
+    /// move before revisiting any is left to the expansion of `untried_actions`. The logarithm is
+    /// still clamped so a parent without visits can't produce NaN, which `f64_cmp` would silently
+    /// rank below everything.
+    ///
+    /// With `Selection::Puct` the bound is instead `Q + c_puct * prior * sqrt(N) / (1 + n)`, and
+    /// with `Selection::Ucb1Tuned` the exploration term is `sqrt(ln N / n * min(1/4, V))`, where
+    /// `V` is an upper bound on the variance of the child's rewards.
+    fn choose_child(&mut self, mover: Player, config: &Config, state: &S) -> Option<&mut Node<S>> {
+        let log_visits = (self.visits as f64 * 2.0).max(1.0).ln();
+        let ln_visits = (self.visits as f64).max(1.0).ln();
+        let sqrt_visits = (self.visits as f64).sqrt();
+        let i = mover.index();
+        let weight = |c: &Node<S>| match config.selection {
+            Selection::Puct { c_puct } => {
+                c.values[i] + c_puct * c.prior * sqrt_visits / (1.0 + c.visits as f64)
+            }
+            Selection::Ucb1 => {
+                c.values[i] + config.exploration * (log_visits / c.visits as f64).sqrt()
+            }
+            Selection::Ucb1Tuned => {
+                let n = c.visits as f64;
+                let spread = c.squares[i] - c.values[i] * c.values[i];
+                let variance = spread + (2.0 * ln_visits / n).sqrt();
+                c.values[i] + config.exploration * (ln_visits / n * variance.min(0.25)).sqrt()
+            }
+        };
+        let candidates = self.children.iter().enumerate().filter(|&(_, c)| c.solved.is_none());
+        let best = self.best_index(candidates, config.tie_break, state, |a, b| {
+            f64_cmp(weight(a), weight(b)).then(a.visits.cmp(&b.visits))
+        });
+        best.map(move |i| &mut self.children[i])
     }
-    fn best_action(&self) -> Option<S::Action> {
-        self.children
-            .iter()
-            .max_by(|a, b| f64_cmp(a.value, b.value))
-            .and_then(|c| c.action)
+    /// The child with the best value, preferring more visits on ties and then `tie_break`. Once
+    /// this node is solved, only the children that prove its value are considered, since a
+    /// lucky playout can give an unproven move as high a value as a proven win.
+    /// `state` is the position at this node.
+    fn best_action(
+        &self,
+        selection: MoveSelection,
+        tie_break: TieBreak,
+        state: &S,
+    ) -> Option<S::Action> {
+        let proof = self.solved.filter(|&v| self.children.iter().any(|c| c.solved == Some(v)));
+        let candidates =
+            self.children.iter().enumerate().filter(|&(_, c)| proof.is_none() || c.solved == proof);
+        let best = self.best_index(candidates, tie_break, state, |a, b| match selection {
+            MoveSelection::Value => f64_cmp(a.value(), b.value()).then(a.visits.cmp(&b.visits)),
+            MoveSelection::Visits => a.visits.cmp(&b.visits).then(f64_cmp(a.value(), b.value())),
+        });
+        best.and_then(|i| self.children[i].action)
     }
-    fn new<R: Rng>(
+    /// The index of the greatest of `candidates` by `cmp`, with exact ties broken by `tie_break`.
+    fn best_index<'a, I, F>(
+        &self,
+        candidates: I,
+        tie_break: TieBreak,
+        state: &S,
+        cmp: F,
+    ) -> Option<usize>
+    where
+        I: Iterator<Item = (usize, &'a Node<S>)>,
+        F: Fn(&Node<S>, &Node<S>) -> Ordering,
+        S: 'a,
+    {
+        // The position of an action among the valid ones, only needed for `LowestIndex`.
+        let rank = |action: Option<S::Action>| {
+            state.valid_actions(state.next_player()).position(|a| Some(a) == action)
+        };
+        let key = |i: usize, seed: u64| splitmix(seed ^ splitmix(self.visits as u64 ^ i as u64));
+        let mut best: Option<(usize, &Node<S>)> = None;
+        for (i, node) in candidates {
+            let replace = match best {
+                None => true,
+                Some((j, other)) => match cmp(node, other) {
+                    Ordering::Greater => true,
+                    Ordering::Less => false,
+                    Ordering::Equal => match tie_break {
+                        TieBreak::FirstExpanded => false,
+                        TieBreak::LowestIndex => rank(node.action) < rank(other.action),
+                        TieBreak::Random(seed) => key(i, seed) > key(j, seed),
+                    },
+                },
+            };
+            if replace {
+                best = Some((i, node));
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+    /// A node for `state`, which `outcome` describes. It has no value until `evaluate` is called.
+    fn new(
         action: Option<S::Action>,
         just_acted: Player,
-        mut state: S,
-        outcome: Outcome<S::Actions>,
+        state: &S,
+        outcome: &Outcome<S::Actions>,
         perspective: Player,
-        rng: &mut R,
     ) -> Node<S> {
-        let value = state.playout(rng, perspective, outcome.clone());
+        let untried_actions = match *outcome {
+            Outcome::Actions(_) => state.ordered_actions(state.next_player()),
+            _ => S::Actions::default(),
+        };
         Node {
             action,
             visits: 1,
-            value,
-            untried_actions: outcome.as_actions(),
+            values: [0.0; NUM_PLAYERS],
+            squares: [0.0; NUM_PLAYERS],
+            untried_actions,
             children: Vec::new(),
             just_acted,
+            perspective,
+            solved: None,
+            prior: 1.0,
+            restricted: false,
         }
     }
+    /// Sets the values of a new node; the values of finished games are exact. Returns how many
+    /// moves the playout made, if there was one.
+    fn evaluate<R: Rng>(
+        &mut self,
+        state: S,
+        outcome: Outcome<S::Actions>,
+        rng: &mut R,
+        config: &Config,
+        evaluator: Option<&dyn Evaluator<S>>,
+    ) -> Option<usize> {
+        let terminal = !matches!(outcome, Outcome::Actions(_));
+        let (values, moves) = leaf_values(state, outcome, rng, config, evaluator);
+        self.values = values;
+        for (square, value) in self.squares.iter_mut().zip(self.values.iter()) {
+            *square = value * value;
+        }
+        if terminal {
+            self.solved = Some(self.values);
+        }
+        moves
+    }
     pub fn shallow_str(&self) -> String {
         format!(
             "Node ( Just = {:?}{:?}, value = {}, visits = {}, untried = {:?}, chidren: {} )",
             self.just_acted,
             self.action,
-            self.value,
+            self.value(),
             self.visits,
             self.untried_actions,
             self.children.len()
@@ -102,7 +322,7 @@ impl<S: State> Node<S> {
     #[allow(dead_code)]
     pub fn print_1_layer(&self) {
         println!("{}", self.shallow_str());
-        for ref child in self.children.iter() {
+        for child in self.children.iter() {
             println!("  {}", child.shallow_str());
         }
     }
@@ -116,8 +336,42 @@ impl<S: State> Node<S> {
     pub fn visits(&self) -> usize {
         self.visits
     }
+    /// The average reward of the tree's perspective player.
     pub fn value(&self) -> f64 {
-        self.value
+        self.values[self.perspective.index()]
+    }
+    /// The average reward of `player`, from either side of the tree.
+    pub fn value_for(&self, player: Player) -> f64 {
+        self.values[player.index()]
+    }
+    /// A 95% Wilson score interval around `value`, treating each visit as a win/loss trial.
+    /// Assumes rewards in [0, 1]; solved nodes have an exact value.
+    pub fn value_confidence(&self) -> (f64, f64) {
+        let p = self.value();
+        if self.solved.is_some() {
+            return (p, p);
+        }
+        let z = 1.96;
+        let n = self.visits as f64;
+        let denominator = 1.0 + z * z / n;
+        let center = (p + z * z / (2.0 * n)) / denominator;
+        let half_width = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt() / denominator;
+        (center - half_width, center + half_width)
+    }
+    /// Whether the game-theoretic value of this node is known, in which case `value` is exact.
+    pub fn is_solved(&self) -> bool {
+        self.solved.is_some()
+    }
+    /// Whether the game is over at this node.
+    fn is_terminal(&self) -> bool {
+        self.children.is_empty() && self.untried_actions.len() == 0 && self.solved.is_some()
+    }
+    /// The action which led to this node, or `None` at the root of a fresh tree.
+    pub fn action(&self) -> Option<S::Action> {
+        self.action
+    }
+    pub fn children(&self) -> &[Node<S>] {
+        &self.children
     }
     pub fn max_depth(&self) -> usize {
         self.children
@@ -126,6 +380,115 @@ impl<S: State> Node<S> {
             .max()
             .unwrap_or(0)
     }
+    /// The line of play expected from here: the most visited child at each step.
+    pub fn principal_variation(&self) -> Vec<S::Action> {
+        let mut pv = Vec::new();
+        let mut node = self;
+        while let Some(child) = node.children.iter().max_by_key(|c| c.visits) {
+            pv.extend(child.action);
+            node = child;
+        }
+        pv
+    }
+    /// Makes `value` report `perspective`'s reward throughout this subtree.
+    fn set_perspective(&mut self, perspective: Player) {
+        self.perspective = perspective;
+        for child in &mut self.children {
+            child.set_perspective(perspective);
+        }
+    }
+    /// Adds this subtree, rooted `depth` moves below the tree's root, to `stats`.
+    fn add_stats(&self, depth: usize, stats: &mut TreeStats) {
+        stats.nodes += 1;
+        if self.children.is_empty() {
+            stats.leaves += 1;
+        }
+        if stats.depths.len() <= depth {
+            stats.depths.resize(depth + 1, 0);
+        }
+        stats.depths[depth] += 1;
+        for child in &self.children {
+            child.add_stats(depth + 1, stats);
+        }
+    }
+    /// Forgets the subtrees below descendants with fewer than `threshold` visits, keeping each
+    /// node's most visited child so that the best lines survive. Their statistics stay, and their
+    /// moves become untried again. `state` is the position at this node.
+    fn prune(&mut self, threshold: usize, state: &S) {
+        let best = (0..self.children.len()).max_by_key(|&i| self.children[i].visits);
+        for (i, child) in self.children.iter_mut().enumerate() {
+            if child.children.is_empty() {
+                continue;
+            }
+            let mut child_state = state.clone();
+            child.action.map(|a| child_state.do_action(a));
+            if child.visits < threshold && Some(i) != best {
+                child.children = Vec::new();
+                child.untried_actions = child_state.ordered_actions(child_state.next_player());
+            } else {
+                child.prune(threshold, &child_state);
+            }
+        }
+    }
+    /// The capacity of the `children` of every node in this subtree.
+    fn slots(&self) -> usize {
+        self.iter().map(|(node, _)| node.children.capacity()).sum()
+    }
+    /// The number of nodes in this subtree, including this one.
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().map(|c| c.size()).sum::<usize>()
+    }
+    /// Every node of this subtree in pre-order, with its depth below this one.
+    pub fn iter(&self) -> NodeIter<'_, S> {
+        NodeIter { stack: vec![(self, 0)] }
+    }
+}
+
+/// A pre-order walk over a subtree, as returned by `Node::iter`.
+pub struct NodeIter<'a, S: State + 'a> {
+    stack: Vec<(&'a Node<S>, usize)>,
+}
+
+impl<'a, S: State> Iterator for NodeIter<'a, S> {
+    type Item = (&'a Node<S>, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, depth) = self.stack.pop()?;
+        // Reversed so that the first child is popped first.
+        self.stack.extend(node.children.iter().rev().map(|c| (c, depth + 1)));
+        Some((node, depth))
+    }
+}
+
+/// Estimates the value of unfinished positions, e.g. with a learned value network. A tree with
+/// an evaluator asks it about each new node instead of playing out a random game.
+pub trait Evaluator<S: State> {
+    /// `player`'s expected reward in `state`, on the scale of the tree's `RewardScheme`.
+    fn evaluate(&self, state: &S, player: Player) -> f64;
+}
+
+/// The values of a new leaf: the evaluator's estimate if there is one and the game isn't over,
+/// or else the result of a playout, with the number of moves it made.
+fn leaf_values<S: State, R: Rng>(
+    mut state: S,
+    outcome: Outcome<S::Actions>,
+    rng: &mut R,
+    config: &Config,
+    evaluator: Option<&dyn Evaluator<S>>,
+) -> ([f64; NUM_PLAYERS], Option<usize>) {
+    match (outcome, evaluator) {
+        (Outcome::Actions(_), Some(evaluator)) => {
+            let player = state.next_player();
+            let value = evaluator.evaluate(&state, player);
+            // The game is zero-sum, so the opponent gets the rest.
+            let mut values = [config.rewards.win + config.rewards.loss - value; NUM_PLAYERS];
+            values[player.index()] = value;
+            (values, None)
+        }
+        (outcome, _) => {
+            let (values, moves) = state.playout(rng, outcome, config);
+            (values, Some(moves))
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -141,6 +504,13 @@ impl Player {
             Player::P2 => Player::P1,
         }
     }
+    /// This player's position in turn order, counting from zero.
+    pub fn index(self) -> usize {
+        match self {
+            Player::P1 => 0,
+            Player::P2 => 1,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -152,39 +522,234 @@ pub enum Outcome<Actions: Clone> {
 }
 
 impl<Actions: Default + Clone> Outcome<Actions> {
-    fn value(&self, player: Player) -> f64 {
-        match (self, player) {
-            (&Outcome::P1Win, Player::P1) => 1.0,
-            (&Outcome::P1Win, Player::P2) => 0.0,
-            (&Outcome::P2Win, Player::P1) => 0.0,
-            (&Outcome::P2Win, Player::P2) => 1.0,
-            _ => 0.5,
-        }
-    }
     pub fn from_player(player: Player) -> Self {
         match player {
             Player::P1 => Outcome::P1Win,
             Player::P2 => Outcome::P2Win,
         }
     }
-    fn as_actions(self) -> Actions {
-        match self {
-            Outcome::Actions(actions) => actions,
-            _ => Actions::default(),
+}
+
+/// The value of a finished game to a player. Every node value in the tree is on this scale, and
+/// the exploration term of the selection formula is tuned for values in [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardScheme {
+    pub win: f64,
+    /// The value of a draw to each player, indexed by `Player::index`. They are equal unless the
+    /// scheme is `handicapped`.
+    pub draws: [f64; NUM_PLAYERS],
+    pub loss: f64,
+}
+
+impl RewardScheme {
+    pub fn new(win: f64, draw: f64, loss: f64) -> Self {
+        RewardScheme {
+            win,
+            draws: [draw; NUM_PLAYERS],
+            loss,
+        }
+    }
+    /// This scheme, but with draws worth `draw` to `player` and the rest to their opponent, e.g.
+    /// to make a strong engine play for a win by counting draws as losses.
+    pub fn handicapped(mut self, player: Player, draw: f64) -> Self {
+        self.draws[player.index()] = draw;
+        self.draws[player.other().index()] = self.win + self.loss - draw;
+        self
+    }
+    /// The value of `outcome` to each player, indexed by `Player::index`.
+    pub fn values<A: Clone>(&self, outcome: &Outcome<A>) -> [f64; NUM_PLAYERS] {
+        [self.value(outcome, Player::P1), self.value(outcome, Player::P2)]
+    }
+    /// The value of `outcome` to `player`. Unfinished games count as draws.
+    pub fn value<A: Clone>(&self, outcome: &Outcome<A>, player: Player) -> f64 {
+        match (outcome, player) {
+            (&Outcome::P1Win, Player::P1) | (&Outcome::P2Win, Player::P2) => self.win,
+            (&Outcome::P1Win, Player::P2) | (&Outcome::P2Win, Player::P1) => self.loss,
+            _ => self.draws[player.index()],
+        }
+    }
+}
+
+impl Default for RewardScheme {
+    fn default() -> Self {
+        RewardScheme::new(1.0, 0.5, 0.0)
+    }
+}
+
+/// Limits how quickly a node may grow new children: with `visits` visits it may have at most
+/// `ceil(c * visits^alpha)`. This lets high-branching nodes refine a few moves before trying
+/// them all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressiveWidening {
+    pub c: f64,
+    pub alpha: f64,
+}
+
+impl ProgressiveWidening {
+    /// Always allows at least one child, so that every node can grow.
+    fn max_children(&self, visits: usize) -> usize {
+        let limit = (self.c * (visits as f64).powf(self.alpha)).ceil();
+        if limit >= 1.0 { limit as usize } else { 1 }
+    }
+}
+
+/// How a node picks which child to search next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    /// The upper confidence bound of UCB1, scaled by `Config::exploration`.
+    Ucb1,
+    /// UCB1 with exploration scaled by each child's observed variance, which often needs less
+    /// exploring. Assumes rewards in [0, 1].
+    Ucb1Tuned,
+    /// AlphaZero's PUCT, which weights exploration by `State::action_priors`.
+    Puct { c_puct: f64 },
+}
+
+/// How the tree picks the move it plays once the search is over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoveSelection {
+    /// The child with the best average reward, which may be barely explored.
+    Value,
+    /// The most visited child, the "robust child".
+    Visits,
+}
+
+impl FromStr for MoveSelection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "value" => Ok(MoveSelection::Value),
+            "visits" => Ok(MoveSelection::Visits),
+            _ => Err(format!("Unknown move selection `{}`", s)),
+        }
+    }
+}
+
+/// How the tree chooses among children that are exactly as good as each other, both while
+/// searching and when picking its move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TieBreak {
+    /// The child expanded first, i.e. earliest in `State::ordered_actions`. For games that order
+    /// moves from the center outward, this prefers central moves.
+    FirstExpanded,
+    /// The child whose action comes first in `State::valid_actions`.
+    LowestIndex,
+    /// A child chosen by hashing this seed with the node's visits, so the choice varies from
+    /// move to move and from seed to seed but is repeatable.
+    Random(u64),
+}
+
+/// A step of the splitmix64 generator, used to scatter `TieBreak::Random`'s choices.
+fn splitmix(x: u64) -> u64 {
+    let z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The settings shared by every node of a search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub rewards: RewardScheme,
+    /// `None` expands every move of a node before revisiting any.
+    pub widening: Option<ProgressiveWidening>,
+    pub selection: Selection,
+    pub move_selection: MoveSelection,
+    pub tie_break: TieBreak,
+    /// Scales the exploration term of UCB1.
+    pub exploration: f64,
+    /// The most moves a playout makes before scoring the game as a draw.
+    pub rollout_depth: Option<usize>,
+    /// The most nodes the tree may hold. Once full, searches only refine existing nodes.
+    pub node_limit: Option<usize>,
+    /// Once the tree's nodes take more bytes than this, `MCTree` prunes rarely visited subtrees
+    /// until they take at most half as many.
+    pub memory_limit: Option<usize>,
+    /// Shrinks each playout result toward a draw by `gamma^moves`, so that quick wins and slow
+    /// losses are preferred. 1.0 disables the discount.
+    pub gamma: f64,
+    /// Playouts still running at this instant stop and are scored as draws. Set by
+    /// `MCTree::search_bounded` for the length of a search.
+    pub deadline: Option<time::Instant>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            rewards: RewardScheme::default(),
+            widening: None,
+            selection: Selection::Ucb1,
+            move_selection: MoveSelection::Value,
+            tie_break: TieBreak::FirstExpanded,
+            exploration: 1.0,
+            rollout_depth: None,
+            node_limit: None,
+            memory_limit: None,
+            gamma: 1.0,
+            deadline: None,
         }
     }
 }
 
+/// How many moves a playout makes between checks of `Config::deadline`.
+const DEADLINE_CHECK_INTERVAL: usize = 16;
+
+/// The default for `State::max_playout_moves`, far longer than any sensible game.
+const MAX_PLAYOUT_MOVES: usize = 10_000;
+
+/// How many times a playout may reach the same position before it's scored as a draw, in games
+/// where `State::repetition_is_draw` holds.
+const REPETITION_LIMIT: usize = 3;
+
 pub trait State: Clone + fmt::Display {
     type Action: Copy + Eq + fmt::Debug;
     type Actions: ExactSizeIterator + Iterator<Item=Self::Action> + Clone + Default + fmt::Debug;
     fn initial() -> Self;
+    fn render(&self) -> String {
+        self.to_string()
+    }
+    /// Renders the state with a label attached to each of the given actions, e.g. the engine's
+    /// evaluation of that move. By default the labels are listed below the board.
+    fn render_with_annotations(&self, annotations: &[(Self::Action, String)]) -> String {
+        let mut rendered = self.render();
+        for &(action, ref label) in annotations {
+            rendered.push_str(&format!("\n{:?}: {}", action, label));
+        }
+        rendered
+    }
     fn do_action(&mut self, action: Self::Action) -> Outcome<Self::Actions>;
     fn next_player(&self) -> Player;
     fn valid_actions(&self, player: Player) -> Self::Actions;
+    /// The valid actions, most promising first. Untried actions are expanded in this order, so
+    /// a good ordering gets strong moves sampled early under tight budgets.
+    fn ordered_actions(&self, player: Player) -> Self::Actions {
+        self.valid_actions(player)
+    }
     fn has_won(&self, player: Player) -> bool;
+    /// The probability of each valid action being the best one, for `Selection::Puct`. Uniform
+    /// by default.
+    fn action_priors(&self) -> Vec<(Self::Action, f64)> {
+        let actions = self.valid_actions(self.next_player());
+        let prior = 1.0 / actions.len() as f64;
+        actions.map(|a| (a, prior)).collect()
+    }
+    /// A hash of the position, for recognizing transpositions. Equal positions must have equal
+    /// keys; states which can't hash themselves cheaply return `None`.
+    fn hash_key(&self) -> Option<u64> {
+        None
+    }
+    /// Whether positions can repeat, in which case a playout that reaches the same position
+    /// `REPETITION_LIMIT` times is a draw. Needs `hash_key`.
+    fn repetition_is_draw() -> bool {
+        false
+    }
+    /// A hard limit on the length of a playout, after which it's scored as a draw, so that a
+    /// game which never ends can't hang the search. Games with a known bound can lower it.
+    fn max_playout_moves() -> usize {
+        MAX_PLAYOUT_MOVES
+    }
     fn outcome(&self) -> Outcome<Self::Actions> {
-        return if self.has_won(Player::P1) {
+        if self.has_won(Player::P1) {
             Outcome::P1Win
         } else if self.has_won(Player::P2) {
             Outcome::P2Win
@@ -193,17 +758,227 @@ pub trait State: Clone + fmt::Display {
             if actions.len() == 0 { Outcome::Draw } else { Outcome::Actions(actions) }
         }
     }
-    fn playout<R: Rng>(&mut self, rng: &mut R, player: Player, mut outcome: Outcome<Self::Actions>) -> f64 {
-        loop {
-            let mut actions = if let Outcome::Actions(a) = outcome {
-                a
-            } else {
-                return outcome.value(player);
-            };
-            let range = Range::new(0, actions.len());
-            let action = actions.nth(range.ind_sample(rng)).unwrap();
-            outcome = self.do_action(action);
+    /// Picks the next move of a playout from the nonempty `actions`. Uniformly random by
+    /// default; games can bias it toward moves that are usually good.
+    fn rollout_action<R: Rng>(&self, mut actions: Self::Actions, rng: &mut R) -> Self::Action {
+        let range = Range::new(0, actions.len());
+        actions.nth(range.ind_sample(rng)).unwrap()
+    }
+    /// Plays random moves until the game ends, `config.rollout_depth` or `max_playout_moves`
+    /// moves have been made or `config.deadline` passes, returning each player's reward
+    /// discounted by `config.gamma` and the number of moves played.
+    fn playout<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        outcome: Outcome<Self::Actions>,
+        config: &Config,
+    ) -> ([f64; NUM_PLAYERS], usize) {
+        run_playout(self, rng, outcome, config, |_| {})
+    }
+    /// Plays a playout from here as `playout` does under the default `Config`, returning
+    /// `player`'s reward and the moves it made, to show what a rollout actually did. It makes the
+    /// same moves as `playout` given an identically seeded `rng`, but is slower, so it's meant for
+    /// debugging rather than searching.
+    fn playout_traced<R: Rng>(&mut self, rng: &mut R, player: Player) -> (f64, Vec<Self::Action>) {
+        let mut moves = Vec::new();
+        let outcome = self.outcome();
+        let config = Config::default();
+        let (values, _) = run_playout(self, rng, outcome, &config, |action| moves.push(action));
+        (values[player.index()], moves)
+    }
+}
+
+/// The playout loop behind `State::playout` and `State::playout_traced`, which calls `on_move`
+/// with each move it makes.
+fn run_playout<S: State, R: Rng, F: FnMut(S::Action)>(
+    state: &mut S,
+    rng: &mut R,
+    mut outcome: Outcome<S::Actions>,
+    config: &Config,
+    mut on_move: F,
+) -> ([f64; NUM_PLAYERS], usize) {
+    let mut depth = 0;
+    let mut seen = HashMap::new();
+    loop {
+        let actions = if let Outcome::Actions(a) = outcome {
+            a
+        } else {
+            let mut values = config.rewards.values(&outcome);
+            if config.gamma != 1.0 {
+                let discount = config.gamma.powi(depth as i32);
+                for (value, draw) in values.iter_mut().zip(config.rewards.draws.iter()) {
+                    *value = draw + (*value - draw) * discount;
+                }
+            }
+            return (values, depth);
+        };
+        let late = depth % DEADLINE_CHECK_INTERVAL == 0 &&
+            config.deadline.is_some_and(|d| time::Instant::now() >= d);
+        let repeated = S::repetition_is_draw() && state.hash_key().is_some_and(|key| {
+            let count = seen.entry(key).or_insert(0);
+            *count += 1;
+            *count >= REPETITION_LIMIT
+        });
+        let too_long = depth >= S::max_playout_moves() ||
+            config.rollout_depth.is_some_and(|max| depth >= max);
+        if late || repeated || too_long {
+            return (config.rewards.values::<S::Actions>(&Outcome::Draw), depth);
         }
+        depth += 1;
+        let action = state.rollout_action(actions, rng);
+        on_move(action);
+        outcome = state.do_action(action);
+    }
+}
+
+/// A candidate root move, as reported by `MCTree::top_moves`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis<A> {
+    pub action: A,
+    pub visits: usize,
+    /// The perspective player's average reward after `action`.
+    pub value: f64,
+    /// The line expected after `action`, not including it.
+    pub pv: Vec<A>,
+}
+
+/// How much the tree prints about its searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Silent,
+    /// A line per search saying how many iterations it ran.
+    Normal,
+    /// That line and the shape of the tree after the search.
+    Debug,
+}
+
+/// How much searching a search did, as returned by `MCTree::search_for` and friends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchStats {
+    pub iterations: usize,
+    pub elapsed: time::Duration,
+    /// How many times the best root move changed, sampled every `STABILITY_CHECK_INTERVAL`
+    /// searches. Frequent changes suggest a difficult position.
+    pub best_move_changes: usize,
+    /// The number of searches after which the final best move was first seen.
+    pub best_move_last_changed_at: usize,
+    /// How many playouts made each number of moves: bucket 0 counts playouts from finished
+    /// games, and bucket `i` those of `2^(i-1)` up to `2^i - 1` moves, with the last bucket
+    /// taking everything longer. Searches that used the evaluator instead aren't counted.
+    pub playout_lengths: [usize; PLAYOUT_BUCKETS],
+}
+
+impl SearchStats {
+    pub fn per_second(&self) -> f64 {
+        self.iterations as f64 / self.elapsed.as_secs_f64()
+    }
+    /// Counts a playout of `moves` moves in `playout_lengths`.
+    fn note_playout(&mut self, moves: usize) {
+        let bucket = (usize::BITS - moves.leading_zeros()) as usize;
+        self.playout_lengths[bucket.min(PLAYOUT_BUCKETS - 1)] += 1;
+    }
+    /// Records `current` as the best move after `self.iterations` searches, where `best` was.
+    fn note_best<A: PartialEq>(&mut self, best: &mut Option<A>, current: Option<A>) {
+        if current != *best {
+            if best.is_some() {
+                self.best_move_changes += 1;
+            }
+            self.best_move_last_changed_at = self.iterations;
+            *best = current;
+        }
+    }
+    /// The stats of this search followed by `next` on the same tree.
+    fn followed_by(self, next: SearchStats) -> SearchStats {
+        let last_changed_at = if next.best_move_last_changed_at > 0 {
+            self.iterations + next.best_move_last_changed_at
+        } else {
+            self.best_move_last_changed_at
+        };
+        let mut playout_lengths = self.playout_lengths;
+        for (count, next) in playout_lengths.iter_mut().zip(&next.playout_lengths) {
+            *count += next;
+        }
+        SearchStats {
+            iterations: self.iterations + next.iterations,
+            elapsed: self.elapsed + next.elapsed,
+            best_move_changes: self.best_move_changes + next.best_move_changes,
+            best_move_last_changed_at: last_changed_at,
+            playout_lengths,
+        }
+    }
+}
+
+/// What became of the search tree when `MCTree::do_action` moved its root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reroot {
+    /// The move had been expanded, so its subtree, with this many playouts, became the tree.
+    Preserved { subtree_visits: usize },
+    /// The move had never been expanded, so the search starts over from the new position.
+    Rebuilt,
+}
+
+/// How many searches pass between checks of the best move for `SearchStats`.
+const STABILITY_CHECK_INTERVAL: usize = 64;
+
+/// How often, in milliseconds, `MCTree::search_for_with_progress` reports.
+const PROGRESS_INTERVAL: u64 = 250;
+
+/// The number of buckets in `SearchStats::playout_lengths`.
+pub const PLAYOUT_BUCKETS: usize = 10;
+
+/// Counts kept up to date as the tree grows, so they needn't be found by walking it.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    /// The number of nodes in the tree, checked against `config.node_limit`.
+    nodes: usize,
+    /// The capacity of every node's `children`, for `memory_bytes`.
+    slots: usize,
+    /// How many moves the last search's playout made, if it made one.
+    playout_moves: Option<usize>,
+}
+
+/// How many playouts per legal move `MCTree::restrict_root_moves` probes with.
+const ROOT_PROBE_VISITS: usize = 32;
+
+/// How long `MCTree::calibrate` probes for, in milliseconds.
+const CALIBRATION_TIME: usize = 100;
+
+/// The shape of a search tree, as reported by `MCTree::tree_stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TreeStats {
+    pub nodes: usize,
+    /// Nodes without any expanded children.
+    pub leaves: usize,
+    /// `depths[d]` is the number of nodes `d` moves below the root.
+    pub depths: Vec<usize>,
+}
+
+impl TreeStats {
+    /// The average number of children of the nodes that have any.
+    pub fn branching_factor(&self) -> f64 {
+        let expanded = self.nodes - self.leaves;
+        if expanded == 0 {
+            0.0
+        } else {
+            (self.nodes - 1) as f64 / expanded as f64
+        }
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} nodes, {} leaves, branching factor {:.2}",
+            self.nodes,
+            self.leaves,
+            self.branching_factor()
+        )?;
+        write!(f, "nodes by depth:")?;
+        for (depth, count) in self.depths.iter().enumerate() {
+            write!(f, " {}:{}", depth, count)?;
+        }
+        Ok(())
     }
 }
 
@@ -212,49 +987,962 @@ pub struct MCTree<S: State, R: Rng> {
     state: S,
     rng: R,
     perspective: Player,
+    config: Config,
+    tally: Tally,
+    /// Replaces playouts at new nodes, if set.
+    evaluator: Option<Box<dyn Evaluator<S>>>,
+    /// The number of searches since the root last moved.
+    searches: usize,
+    /// Whether searches print a summary line.
+    verbosity: Verbosity,
 }
 
-impl<S: State> MCTree<S, rand::ThreadRng> {
-    pub fn search_for(&mut self, milliseconds: usize) {
+impl<S: State, R: Rng> MCTree<S, R> {
+    /// Searches until `budget` has passed.
+    pub fn search(&mut self, budget: time::Duration) -> SearchStats {
+        self.search_timed(budget, usize::MAX, &AtomicBool::new(false), |_, _| {})
+    }
+    /// `search` with the budget in milliseconds.
+    pub fn search_for(&mut self, milliseconds: usize) -> SearchStats {
+        self.search(time::Duration::from_millis(milliseconds as u64))
+    }
+    /// `search_for`, calling `progress` with the stats so far and the current best move about
+    /// every `PROGRESS_INTERVAL` milliseconds, e.g. so an interactive player can show that the
+    /// engine is thinking and what it's leaning toward.
+    pub fn search_for_with_progress<F>(&mut self, milliseconds: usize, progress: F) -> SearchStats
+    where
+        F: FnMut(&SearchStats, Option<S::Action>),
+    {
+        let budget = time::Duration::from_millis(milliseconds as u64);
+        self.search_timed(budget, usize::MAX, &AtomicBool::new(false), progress)
+    }
+    /// Searches until `milliseconds` have passed or `max_iterations` searches are done, whichever
+    /// comes first. A playout still running at the deadline is cut short, so a slow game can't
+    /// overshoot the budget by a whole playout.
+    pub fn search_bounded(&mut self, milliseconds: usize, max_iterations: usize) -> SearchStats {
+        self.search_bounded_with_stop(milliseconds, max_iterations, &AtomicBool::new(false))
+    }
+    /// Like `search_bounded`, but also returns once `stop` is set, e.g. by a UI's cancel button.
+    /// The flag is checked between searches.
+    pub fn search_bounded_with_stop(
+        &mut self,
+        milliseconds: usize,
+        max_iterations: usize,
+        stop: &AtomicBool,
+    ) -> SearchStats {
+        let budget = time::Duration::from_millis(milliseconds as u64);
+        self.search_timed(budget, max_iterations, stop, |_, _| {})
+    }
+    fn search_timed<F: FnMut(&SearchStats, Option<S::Action>)>(
+        &mut self,
+        budget: time::Duration,
+        max_iterations: usize,
+        stop: &AtomicBool,
+        progress: F,
+    ) -> SearchStats {
         let start = time::Instant::now();
-        let duration = time::Duration::from_millis(milliseconds as u64);
+        let deadline = start + budget;
+        self.config.deadline = Some(deadline);
+        let keep_going = |searches| {
+            searches < max_iterations && time::Instant::now() < deadline &&
+                !stop.load(atomic::Ordering::Relaxed)
+        };
+        let stats = self.search_tracked(keep_going, progress);
+        self.config.deadline = None;
+        let (searches, elapsed) = (stats.iterations, stats.elapsed.as_millis());
+        self.report(format_args!("Did {} searches in {} milliseconds", searches, elapsed));
+        stats
+    }
+    /// Briefly searches to measure this machine's speed on this position, and estimates how
+    /// long `target_iterations` searches would take. The probe's searches stay in the tree.
+    pub fn calibrate(&mut self, target_iterations: usize) -> time::Duration {
+        let stats = self.search_for(CALIBRATION_TIME);
+        let per_second = stats.per_second().max(1.0);
+        time::Duration::from_secs_f64(target_iterations as f64 / per_second)
+    }
+    /// Runs exactly `iterations` searches. Unlike `search_for`, the result depends only on the
+    /// state and the rng, so a seeded tree searches identically on every run.
+    pub fn search_n(&mut self, iterations: usize) -> SearchStats {
+        let stats = self.search_tracked(|searches| searches < iterations, |_, _| {});
+        self.report(format_args!("Did {} searches", iterations));
+        stats
+    }
+    /// Runs `iterations` more searches without printing anything and returns `root_value`, for
+    /// screening many positions cheaply. The searches stay in the tree, so a later search of the
+    /// same position picks up where this left off.
+    pub fn quick_eval(&mut self, iterations: usize) -> f64 {
+        self.search_while(|searches| searches < iterations);
+        self.root_value()
+    }
+    /// Searches for as long as `keep_going`, given the number of searches done so far, says to,
+    /// and returns that number. Reads no clock and prints nothing, so hosts without either (e.g.
+    /// WASM, searching a slice per animation frame) can drive the search themselves.
+    pub fn search_while<F: FnMut(usize) -> bool>(&mut self, mut keep_going: F) -> usize {
         let mut searches = 0;
-        while start.elapsed() < duration {
+        while keep_going(searches) {
             searches += 1;
             self.iter();
         }
-        println!("Did {} searches in {} milliseconds", searches, milliseconds);
+        searches
+    }
+    /// `search_while`, but timed and tracking how the best move changes, which it passes to
+    /// `progress` every `PROGRESS_INTERVAL` milliseconds.
+    fn search_tracked<F, P>(&mut self, mut keep_going: F, mut progress: P) -> SearchStats
+    where
+        F: FnMut(usize) -> bool,
+        P: FnMut(&SearchStats, Option<S::Action>),
+    {
+        let start = time::Instant::now();
+        let interval = time::Duration::from_millis(PROGRESS_INTERVAL);
+        let mut next_progress = start + interval;
+        let mut stats = SearchStats {
+            iterations: 0,
+            elapsed: time::Duration::default(),
+            best_move_changes: 0,
+            best_move_last_changed_at: 0,
+            playout_lengths: [0; PLAYOUT_BUCKETS],
+        };
+        let mut best = self.best_action();
+        while keep_going(stats.iterations) {
+            stats.iterations += 1;
+            self.iter();
+            if let Some(moves) = self.tally.playout_moves.take() {
+                stats.note_playout(moves);
+            }
+            if stats.iterations.is_multiple_of(STABILITY_CHECK_INTERVAL) {
+                stats.note_best(&mut best, self.best_action());
+                let now = time::Instant::now();
+                if now >= next_progress {
+                    stats.elapsed = now - start;
+                    progress(&stats, best);
+                    next_progress = now + interval;
+                }
+            }
+        }
+        stats.note_best(&mut best, self.best_action());
+        stats.elapsed = start.elapsed();
+        stats
+    }
+    /// Prints `summary` of a search, and at `Verbosity::Debug` the shape of the tree after it.
+    fn report(&self, summary: fmt::Arguments) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("{}", summary);
+        }
+        if self.verbosity >= Verbosity::Debug {
+            println!("{}", self.tree_stats());
+        }
+    }
+    /// Searches until `stop` is set, e.g. by another thread once the opponent has moved.
+    pub fn search_with_stop(&mut self, stop: &AtomicBool) -> SearchStats {
+        let keep_going = |_| !stop.load(atomic::Ordering::Relaxed);
+        let stats = self.search_tracked(keep_going, |_, _| {});
+        self.report(format_args!("Did {} searches while waiting", stats.iterations));
+        stats
+    }
+    /// Searches for as long as `tm` allots to this move. A short probe search first measures how
+    /// contested the position is; close positions then get more time and clear ones less.
+    pub fn search_managed(&mut self, tm: &TimeManager) -> SearchStats {
+        let start = time::Instant::now();
+        let legal_actions = self.root.children.len() + self.root.untried_actions.len();
+        let base = tm.base_allocation(legal_actions);
+        let probe = base / 4;
+        let probe_stats = self.search_for(probe);
+        let budget = tm.scale(base, self.root_entropy());
+        let mut stats = probe_stats.followed_by(self.search_for(budget.saturating_sub(probe)));
+        if self.root.children.is_empty() {
+            stats = stats.followed_by(self.search_n(1));
+        }
+        stats.elapsed = start.elapsed();
+        tm.record(stats.elapsed.as_millis() as usize);
+        stats
+    }
+    /// The entropy of the visit distribution over root moves, normalized to [0, 1].
+    fn root_entropy(&self) -> f64 {
+        let children = &self.root.children;
+        if children.len() < 2 {
+            return 0.0;
+        }
+        let total: usize = children.iter().map(|c| c.visits).sum();
+        let entropy: f64 = children
+            .iter()
+            .map(|c| c.visits as f64 / total as f64)
+            .map(|p| -p * p.ln())
+            .sum();
+        entropy / (children.len() as f64).ln()
+    }
+    /// The `n` most visited root moves, each with the line expected to follow it.
+    pub fn top_moves(&self, n: usize) -> Vec<MoveAnalysis<S::Action>> {
+        let mut children: Vec<&Node<S>> = self.root.children.iter().collect();
+        children.sort_by_key(|c| Reverse(c.visits));
+        children
+            .into_iter()
+            .take(n)
+            .filter_map(|c| {
+                c.action.map(|action| {
+                    MoveAnalysis {
+                        action,
+                        visits: c.visits,
+                        value: c.value(),
+                        pv: c.principal_variation(),
+                    }
+                })
+            })
+            .collect()
+    }
+    pub fn tree_stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        self.root.add_stats(0, &mut stats);
+        stats
+    }
+    /// How many moves ahead every line has been expanded.
+    pub fn min_depth(&self) -> usize {
+        self.root.min_depth()
+    }
+    /// How many moves ahead the deepest line has been expanded.
+    pub fn max_depth(&self) -> usize {
+        self.root.max_depth()
+    }
+    /// Estimates how many more moves the game will last: the depth of the finished games in the
+    /// tree, weighted by how often the search reached them. Until the search reaches the end of
+    /// any game this falls back to the length of the principal variation, which is only a lower
+    /// bound.
+    pub fn expected_game_length(&self) -> f64 {
+        let (visits, weighted_depth) = self
+            .root
+            .iter()
+            .filter(|&(node, _)| node.is_terminal())
+            .fold((0, 0), |(visits, sum), (node, depth)| {
+                (visits + node.visits, sum + node.visits * depth)
+            });
+        if visits == 0 {
+            self.root.principal_variation().len() as f64
+        } else {
+            weighted_depth as f64 / visits as f64
+        }
+    }
+    pub fn root_visits(&self) -> usize {
+        self.root.visits()
+    }
+    /// The perspective player's average reward from the current position.
+    pub fn root_value(&self) -> f64 {
+        self.root.value()
+    }
+    /// `player`'s average reward from the current position, e.g. to show what the opponent
+    /// thinks of it.
+    pub fn root_value_for(&self, player: Player) -> f64 {
+        self.root.value_for(player)
+    }
+    /// The player whose moves this tree chooses.
+    pub fn perspective(&self) -> Player {
+        self.perspective
+    }
+    /// `(action, visits, value)` for every expanded root move, most visited first.
+    pub fn root_evaluations(&self) -> Vec<(S::Action, usize, f64)> {
+        let mut evaluations: Vec<_> = self.root
+            .children
+            .iter()
+            .filter_map(|c| c.action.map(|a| (a, c.visits, c.value())))
+            .collect();
+        evaluations.sort_by_key(|e| Reverse(e.1));
+        evaluations
+    }
+    /// Draws a root move with probability proportional to `visits^(1/temperature)`. A
+    /// temperature of zero always picks the most visited move.
+    pub fn sample_action<G: Rng>(&self, temperature: f64, rng: &mut G) -> Option<S::Action> {
+        let evaluations = self.root_evaluations();
+        let most_visits = match evaluations.first() {
+            Some(&(_, visits, _)) if temperature > 0.0 => visits as f64,
+            Some(&(action, _, _)) => return Some(action),
+            None => return None,
+        };
+        // Visits are scaled by the maximum first so that low temperatures don't overflow.
+        let weights: Vec<f64> = evaluations
+            .iter()
+            .map(|&(_, visits, _)| (visits as f64 / most_visits).powf(1.0 / temperature))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let mut target = Range::new(0.0, total).ind_sample(rng);
+        for (&(action, _, _), weight) in evaluations.iter().zip(weights) {
+            if target < weight {
+                return Some(action);
+            }
+            target -= weight;
+        }
+        evaluations.last().map(|e| e.0)
+    }
+    /// The result of the game if it is over at the root, or `None` if there are moves left.
+    pub fn is_terminal(&self) -> Option<Outcome<S::Actions>> {
+        match self.root_state().outcome() {
+            Outcome::Actions(_) => None,
+            outcome => Some(outcome),
+        }
+    }
+    /// The position at the root. `state` lags one move behind it, since nodes apply their own
+    /// action when selected.
+    fn root_state(&self) -> S {
+        let mut state = self.state.clone();
+        if let Some(action) = self.root.action {
+            state.do_action(action);
+        }
+        state
     }
     fn iter(&mut self) {
+        self.searches += 1;
+        self.tally.playout_moves = None;
         self.root.select(
             self.state.clone(),
             &mut self.rng,
-            self.perspective,
+            &self.config,
+            &mut self.tally,
+            self.evaluator.as_deref(),
         );
+        if self.config.memory_limit.is_some_and(|limit| self.memory_bytes() > limit) {
+            let limit = self.config.memory_limit.unwrap_or(0);
+            self.prune_to(limit / 2);
+        }
+    }
+    /// Prunes subtrees with ever more visits until the tree takes at most `bytes`, or only the
+    /// root's children and their best lines are left.
+    fn prune_to(&mut self, bytes: usize) {
+        let state = self.root_state();
+        let mut threshold = 2;
+        while self.memory_bytes() > bytes && threshold <= self.root.visits {
+            self.root.prune(threshold, &state);
+            self.tally.slots = self.root.slots();
+            threshold *= 2;
+        }
+        self.tally.nodes = self.root.size();
+    }
+    /// The memory the tree's nodes take, including the spare capacity of their children's
+    /// `Vec`s. Memory the `State::Actions` iterators allocate themselves isn't counted.
+    pub fn memory_bytes(&self) -> usize {
+        (1 + self.tally.slots) * mem::size_of::<Node<S>>()
+    }
+    /// Expands every root move not yet expanded, with one playout each, so that `best_action`
+    /// ranks all of them even after a very short search. Ignores progressive widening and the
+    /// node limit to do so.
+    pub fn expand_root_fully(&mut self) {
+        let widening = self.config.widening.take();
+        let node_limit = self.config.node_limit.take();
+        // A solved root is never expanded further.
+        while self.root.untried_actions.len() > 0 && self.root.solved.is_none() {
+            self.iter();
+        }
+        self.config.widening = widening;
+        self.config.node_limit = node_limit;
+    }
+    /// Trades strength for speed by searching only `k` root moves: after a brief probe of
+    /// `ROOT_PROBE_VISITS` playouts per legal move, every root move but the `k` most visited is
+    /// dropped, so later searches spend all their time on the rest. The restriction lasts until
+    /// the root moves on.
+    ///
+    /// The probe is short, so it can easily drop the best move, especially a quiet one whose
+    /// strength only shows after a deep search. Positions the probe solves are left alone, and
+    /// afterwards only a proven win solves the root: proving the kept moves lost or drawn says
+    /// nothing about the dropped ones.
+    ///
+    /// Panics if `k` is 0, which would leave no move to play.
+    pub fn restrict_root_moves(&mut self, k: usize) {
+        assert!(k > 0, "restricting the root to no moves");
+        self.expand_root_fully();
+        let probe = self.root.children.len() * ROOT_PROBE_VISITS;
+        self.search_n(probe);
+        if self.root.solved.is_some() || self.root.children.len() <= k {
+            return;
+        }
+        let mut by_visits: Vec<usize> = (0..self.root.children.len()).collect();
+        by_visits.sort_by_key(|&i| Reverse(self.root.children[i].visits));
+        let mut keep = vec![false; by_visits.len()];
+        for &i in &by_visits[..k] {
+            keep[i] = true;
+        }
+        // Kept in their original order, which `TieBreak::FirstExpanded` depends on.
+        let mut kept = keep.iter();
+        self.root.children.retain(|_| kept.next() == Some(&true));
+        self.root.untried_actions = S::Actions::default();
+        self.root.restricted = true;
+        self.tally.nodes = self.root.size();
+        self.tally.slots = self.root.slots();
+    }
+    /// Whether the most visited root move is worth more than `margin` less than the root's
+    /// running value, suggesting the search has only just found a refutation of it and hasn't yet
+    /// settled on an alternative.
+    pub fn best_is_collapsing(&self, margin: f64) -> bool {
+        self.root
+            .children
+            .iter()
+            .max_by_key(|c| c.visits)
+            .is_some_and(|c| c.value() < self.root.value() - margin)
+    }
+    /// The root move `choose_and_do_action` would play, if any has been expanded.
+    pub fn best_action(&self) -> Option<S::Action> {
+        let config = &self.config;
+        self.root.best_action(config.move_selection, config.tie_break, &self.root_state())
     }
     pub fn choose_and_do_action(&mut self) -> S::Action {
-        assert!(self.perspective != self.root.just_acted);
-        let action = self.root.best_action().unwrap();
+        assert!(
+            self.perspective != self.root.just_acted,
+            "choose_and_do_action called on the opponent's turn"
+        );
+        let action = self.best_action().unwrap();
         self.do_action(action);
         action
     }
-    pub fn do_action(&mut self, action: S::Action) {
-        let index = self.root
-            .children
-            .iter()
-            .position(|c| c.action == Some(action))
-            .unwrap();
-        let new_root = self.root.children.remove(index);
+    /// Moves the root to the child for `action`, keeping its subtree. If `action` was never
+    /// expanded the search starts over from the new position. Returns which of the two happened.
+    pub fn do_action(&mut self, action: S::Action) -> Reroot {
+        let index = self.root.children.iter().position(|c| c.action == Some(action));
+        let (new_root, reroot) = match index {
+            Some(index) => {
+                let child = self.root.children.remove(index);
+                let subtree_visits = child.visits;
+                (child, Reroot::Preserved { subtree_visits })
+            }
+            None => {
+                let mut state = self.root_state();
+                let outcome = state.do_action(action);
+                let mover = self.root.just_acted.other();
+                let mut node = Node::new(Some(action), mover, &state, &outcome, self.perspective);
+                let evaluator = self.evaluator.as_deref();
+                node.evaluate(state, outcome, &mut self.rng, &self.config, evaluator);
+                (node, Reroot::Rebuilt)
+            }
+        };
         let old_root = mem::replace(&mut self.root, new_root);
         old_root.action.map(|a| self.state.do_action(a));
+        self.tally.nodes = self.root.size();
+        self.tally.slots = self.root.slots();
+        self.searches = 0;
+        reroot
     }
-    pub fn new(state: S, perspective: Player, to_move: Player) -> Self {
-        let mut rng = rand::thread_rng();
+    /// Creates a tree for choosing `me`'s moves from `state`, with the player to move taken from
+    /// the state.
+    pub fn for_player_with_rng(state: S, me: Player, rng: R) -> Self {
+        let to_move = state.next_player();
+        MCTree::with_rng(state, me, to_move, rng)
+    }
+    /// See `with_config` for the meaning of the arguments.
+    pub fn with_rng(state: S, perspective: Player, to_move: Player, rng: R) -> Self {
+        MCTree::with_rewards(state, perspective, to_move, rng, RewardScheme::default())
+    }
+    pub fn with_rewards(
+        state: S,
+        perspective: Player,
+        to_move: Player,
+        rng: R,
+        rewards: RewardScheme,
+    ) -> Self {
+        let config = Config {
+            rewards,
+            ..Config::default()
+        };
+        MCTree::with_config(state, perspective, to_move, rng, config)
+    }
+    /// Creates a tree for choosing `perspective`'s moves from `state`, which may be any position.
+    /// `to_move` must be `state.next_player()`: the root is recorded as reached by a move of the
+    /// other player, and `choose_and_do_action` may only be called when it is `perspective`'s
+    /// turn.
+    pub fn with_config(
+        state: S,
+        perspective: Player,
+        to_move: Player,
+        mut rng: R,
+        config: Config,
+    ) -> Self {
+        debug_assert_eq!(
+            state.next_player(),
+            to_move,
+            "the tree's player to move disagrees with the state"
+        );
+        let outcome = state.outcome();
+        let mut root = Node::new(None, to_move.other(), &state, &outcome, perspective);
+        root.evaluate(state.clone(), outcome, &mut rng, &config, None);
         MCTree {
-            root: Node::new(None, to_move.other(), state.clone(), state.outcome(), perspective, &mut rng),
+            root,
             state,
             rng,
             perspective,
+            config,
+            tally: Tally {
+                nodes: 1,
+                ..Tally::default()
+            },
+            evaluator: None,
+            searches: 0,
+            verbosity: Verbosity::Normal,
+        }
+    }
+    /// A copy of `prior`, e.g. a deeply searched opening, that chooses `perspective`'s moves.
+    /// Searches carry on from `prior`'s statistics, so games that all start from the same
+    /// position needn't each search it from scratch. Takes `prior`'s config and verbosity, but
+    /// not its evaluator, which can't be copied.
+    pub fn with_prior_tree<G: Rng>(prior: &MCTree<S, G>, perspective: Player, rng: R) -> Self {
+        let mut root = prior.root.clone();
+        root.set_perspective(perspective);
+        // Cloning trims each `Vec` to its length, so the copy may be smaller than `prior`.
+        let slots = root.slots();
+        MCTree {
+            root,
+            state: prior.state.clone(),
+            rng,
+            perspective,
+            config: prior.config,
+            tally: Tally {
+                slots,
+                ..prior.tally
+            },
+            evaluator: None,
+            searches: 0,
+            verbosity: prior.verbosity,
+        }
+    }
+    /// Starts over from `state`, as `with_config` would, but keeps this tree's rng, config,
+    /// evaluator and verbosity. The old nodes are dropped here rather than with the tree.
+    pub fn reset(&mut self, state: S, perspective: Player, to_move: Player) {
+        debug_assert_eq!(
+            state.next_player(),
+            to_move,
+            "the tree's player to move disagrees with the state"
+        );
+        let outcome = state.outcome();
+        let mut root = Node::new(None, to_move.other(), &state, &outcome, perspective);
+        let evaluator = self.evaluator.as_deref();
+        root.evaluate(state.clone(), outcome, &mut self.rng, &self.config, evaluator);
+        self.root = root;
+        self.state = state;
+        self.perspective = perspective;
+        self.tally = Tally {
+            nodes: 1,
+            ..Tally::default()
+        };
+        self.searches = 0;
+    }
+    /// Evaluates all further nodes with `evaluator` instead of playouts.
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator<S>>) {
+        self.evaluator = Some(evaluator);
+    }
+    /// Enables progressive widening for all further expansions.
+    pub fn set_progressive_widening(&mut self, c: f64, alpha: f64) {
+        self.config.widening = Some(ProgressiveWidening { c, alpha });
+    }
+    /// Replaces the rewards of finished games, e.g. with a `RewardScheme::handicapped` one. Best
+    /// done before searching, since values already in the tree keep the old scale.
+    pub fn set_rewards(&mut self, rewards: RewardScheme) {
+        self.config.rewards = rewards;
+    }
+    /// Discounts playout results by `gamma` per move; see `Config::gamma`.
+    pub fn set_gamma(&mut self, gamma: f64) {
+        self.config.gamma = gamma;
+    }
+    pub fn set_move_selection(&mut self, selection: MoveSelection) {
+        self.config.move_selection = selection;
+    }
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.config.tie_break = tie_break;
+    }
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        self.config.memory_limit = bytes;
+    }
+    pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+    }
+    /// `set_verbosity` with `Verbosity::Normal` or `Verbosity::Silent`.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbosity = if verbose { Verbosity::Normal } else { Verbosity::Silent };
+    }
+}
+
+/// How many root moves the `Display` impl of `MCTree` lists.
+const DISPLAYED_MOVES: usize = 3;
+
+impl<S: State, R: Rng> fmt::Display for MCTree<S, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.root_state())?;
+        writeln!(
+            f,
+            "{:?} to move, searching for {:?}",
+            self.root.just_acted.other(),
+            self.perspective
+        )?;
+        write!(
+            f,
+            "{} visits, value {:.3}",
+            self.root_visits(),
+            self.root_value()
+        )?;
+        for (action, visits, value) in self.root_evaluations().into_iter().take(DISPLAYED_MOVES) {
+            write!(f, "\n  {:?}: {} visits, value {:.3}", action, visits, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Like `Display`, but lists every root move in `Node::print_1_layer` form.
+impl<S: State, R: Rng> fmt::Debug for MCTree<S, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.root_state())?;
+        writeln!(f, "perspective {:?}, config {:?}", self.perspective, self.config)?;
+        write!(f, "{}", self.root.shallow_str())?;
+        for child in &self.root.children {
+            write!(f, "\n  {}", child.shallow_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: State> MCTree<S, rand::ThreadRng> {
+    /// See `with_config` for how the arguments must agree; `for_player` can't get them wrong.
+    pub fn new(state: S, perspective: Player, to_move: Player) -> Self {
+        MCTree::with_rng(state, perspective, to_move, rand::thread_rng())
+    }
+    /// Creates a tree for choosing `me`'s moves from `state`.
+    pub fn for_player(state: S, me: Player) -> Self {
+        MCTree::for_player_with_rng(state, me, rand::thread_rng())
+    }
+}
+
+/// Collects search settings before creating an `MCTree`. Trees built without a `seed` are
+/// seeded randomly.
+#[derive(Debug, Clone)]
+pub struct MctsBuilder<S: State> {
+    config: Config,
+    seed: Option<u64>,
+    verbosity: Verbosity,
+    state: PhantomData<S>,
+}
+
+impl<S: State> MctsBuilder<S> {
+    pub fn new() -> Self {
+        MctsBuilder {
+            config: Config::default(),
+            seed: None,
+            verbosity: Verbosity::Normal,
+            state: PhantomData,
+        }
+    }
+    pub fn exploration(mut self, c: f64) -> Self {
+        self.config.exploration = c;
+        self
+    }
+    /// Selects children by PUCT with the given constant instead of UCB1.
+    pub fn puct(mut self, c_puct: f64) -> Self {
+        self.config.selection = Selection::Puct { c_puct };
+        self
+    }
+    /// Selects children by UCB1-Tuned instead of UCB1.
+    pub fn ucb1_tuned(mut self) -> Self {
+        self.config.selection = Selection::Ucb1Tuned;
+        self
+    }
+    pub fn rollout_depth(mut self, depth: usize) -> Self {
+        self.config.rollout_depth = Some(depth);
+        self
+    }
+    pub fn node_limit(mut self, nodes: usize) -> Self {
+        self.config.node_limit = Some(nodes);
+        self
+    }
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.config.memory_limit = Some(bytes);
+        self
+    }
+    pub fn rewards(mut self, rewards: RewardScheme) -> Self {
+        self.config.rewards = rewards;
+        self
+    }
+    pub fn progressive_widening(mut self, c: f64, alpha: f64) -> Self {
+        self.config.widening = Some(ProgressiveWidening { c, alpha });
+        self
+    }
+    pub fn gamma(mut self, gamma: f64) -> Self {
+        self.config.gamma = gamma;
+        self
+    }
+    pub fn move_selection(mut self, selection: MoveSelection) -> Self {
+        self.config.move_selection = selection;
+        self
+    }
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.config.tie_break = tie_break;
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+    /// `verbosity` with `Verbosity::Normal` or `Verbosity::Silent`.
+    pub fn verbose(self, verbose: bool) -> Self {
+        self.verbosity(if verbose { Verbosity::Normal } else { Verbosity::Silent })
+    }
+    pub fn build(self, state: S, perspective: Player, to_move: Player) -> MCTree<S, rand::StdRng> {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let rng = seeded_rng(seed);
+        let mut tree = MCTree::with_config(state, perspective, to_move, rng, self.config);
+        tree.set_verbosity(self.verbosity);
+        tree
+    }
+}
+
+impl<S: State> Default for MctsBuilder<S> {
+    fn default() -> Self {
+        MctsBuilder::new()
+    }
+}
+
+/// An rng whose sequence is fully determined by `seed`, for reproducible searches.
+pub fn seeded_rng(seed: u64) -> rand::StdRng {
+    rand::SeedableRng::from_seed(&[seed as usize][..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// P1 wins at once with action 0, while any other action hands P2 a coin flip: P2's action
+    /// 0 wins for P2 and action 1 for P1. The winning move is expanded last.
+    #[derive(Clone, Debug)]
+    struct WinInOne {
+        moves: Vec<u8>,
+    }
+
+    impl fmt::Display for WinInOne {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:?}", self.moves)
+        }
+    }
+
+    impl State for WinInOne {
+        type Action = u8;
+        type Actions = std::vec::IntoIter<u8>;
+        fn initial() -> Self {
+            WinInOne { moves: Vec::new() }
+        }
+        fn do_action(&mut self, action: u8) -> Outcome<Self::Actions> {
+            self.moves.push(action);
+            self.outcome()
+        }
+        fn next_player(&self) -> Player {
+            if self.moves.len().is_multiple_of(2) { Player::P1 } else { Player::P2 }
+        }
+        fn valid_actions(&self, _: Player) -> Self::Actions {
+            match (self.moves.len(), self.has_won(Player::P1) || self.has_won(Player::P2)) {
+                (0, _) => vec![1, 2, 3, 0].into_iter(),
+                (1, false) => vec![0, 1].into_iter(),
+                _ => Vec::new().into_iter(),
+            }
+        }
+        fn has_won(&self, player: Player) -> bool {
+            matches!(
+                (&self.moves[..], player),
+                ([0], Player::P1) | ([_, 1], Player::P1) | ([_, 0], Player::P2)
+            )
+        }
+    }
+
+    /// A race to a total of 4: each player adds 1 or 2 in turn, and whoever reaches 4 wins.
+    /// Playouts always add 1, so every search is fully determined.
+    #[derive(Clone, Debug)]
+    struct Race {
+        total: u8,
+        next: Player,
+    }
+
+    impl fmt::Display for Race {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.total)
+        }
+    }
+
+    impl State for Race {
+        type Action = u8;
+        type Actions = std::vec::IntoIter<u8>;
+        fn initial() -> Self {
+            Race { total: 0, next: Player::P1 }
+        }
+        fn do_action(&mut self, action: u8) -> Outcome<Self::Actions> {
+            self.total += action;
+            self.next = self.next.other();
+            self.outcome()
+        }
+        fn next_player(&self) -> Player {
+            self.next
+        }
+        fn valid_actions(&self, _: Player) -> Self::Actions {
+            if self.total >= 4 { Vec::new() } else { vec![1, 2] }.into_iter()
+        }
+        fn has_won(&self, player: Player) -> bool {
+            self.total >= 4 && self.next != player
+        }
+        fn rollout_action<R: Rng>(&self, _: Self::Actions, _: &mut R) -> u8 {
+            1
+        }
+    }
+
+    /// Twenty moves of 0, 1 or 2, after which P1 wins if the total is even: far too long for a
+    /// short search to solve.
+    #[derive(Clone, Debug)]
+    struct Parity {
+        moves: u8,
+        total: u8,
+    }
+
+    impl fmt::Display for Parity {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} after {} moves", self.total, self.moves)
+        }
+    }
+
+    impl State for Parity {
+        type Action = u8;
+        type Actions = std::vec::IntoIter<u8>;
+        fn initial() -> Self {
+            Parity { moves: 0, total: 0 }
+        }
+        fn do_action(&mut self, action: u8) -> Outcome<Self::Actions> {
+            self.moves += 1;
+            self.total += action;
+            self.outcome()
+        }
+        fn next_player(&self) -> Player {
+            if self.moves.is_multiple_of(2) { Player::P1 } else { Player::P2 }
+        }
+        fn valid_actions(&self, _: Player) -> Self::Actions {
+            if self.moves < 20 { vec![0, 1, 2] } else { Vec::new() }.into_iter()
+        }
+        fn has_won(&self, player: Player) -> bool {
+            self.moves == 20 && self.total.is_multiple_of(2) == (player == Player::P1)
+        }
+    }
+
+    #[test]
+    fn traced_playouts_match_playouts() {
+        for seed in 0..8 {
+            let (value, moves) = Parity::initial().playout_traced(&mut seeded_rng(seed), Player::P1);
+            let mut state = Parity::initial();
+            let outcome = state.outcome();
+            let config = Config::default();
+            let (values, depth) = state.playout(&mut seeded_rng(seed), outcome, &config);
+            assert_eq!((value, moves.len()), (values[0], depth));
+            let mut replayed = Parity::initial();
+            for &action in &moves {
+                replayed.do_action(action);
+            }
+            assert_eq!(replayed.total, state.total);
+        }
+    }
+
+    #[test]
+    fn race_search_has_exact_statistics() {
+        let mut tree = MctsBuilder::new().seed(576).verbose(false).build(
+            Race::initial(),
+            Player::P1,
+            Player::P1,
+        );
+        // The root's own playout goes 1, 2, 3, 4, so P2 wins.
+        assert_eq!((tree.root_visits(), tree.root_value()), (1, 0.0));
+        // Expanding 1 leaves P2 at 1 to go 2, 3, 4: another P2 win.
+        tree.search_n(1);
+        assert_eq!((tree.root_visits(), tree.root_value()), (2, 0.0));
+        // Expanding 2 leaves P2 at 2 to go 3, 4: P1 wins.
+        tree.search_n(1);
+        assert_eq!((tree.root_visits(), tree.root_value()), (3, 1.0 / 3.0));
+        assert_eq!(tree.root_evaluations(), vec![(1, 1, 0.0), (2, 1, 1.0)]);
+        // The won move 2 is selected again and P2's reply of 1 is expanded, leaving P1 at 3 to
+        // go 4 and win.
+        tree.search_n(1);
+        assert_eq!((tree.root_visits(), tree.root_value()), (4, 0.5));
+        assert_eq!(tree.root_evaluations(), vec![(2, 2, 1.0), (1, 1, 0.0)]);
+        // Then P2's reply of 2 is expanded, which wins at once and proves that 2 loses for P1.
+        tree.search_n(1);
+        assert_eq!(tree.root_visits(), 5);
+        let two = tree.root.children().iter().find(|c| c.action() == Some(2)).unwrap();
+        assert!(two.is_solved());
+        assert_eq!(two.value(), 0.0);
+    }
+
+    #[test]
+    fn plays_a_proven_win_over_lucky_moves() {
+        for seed in 0..16 {
+            let mut tree = MctsBuilder::new().seed(seed).verbose(false).build(
+                WinInOne::initial(),
+                Player::P1,
+                Player::P1,
+            );
+            tree.search_n(10);
+            assert!(tree.root.is_solved());
+            assert_eq!(tree.best_action(), Some(0), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn a_fresh_parent_tries_every_child_before_revisiting_any() {
+        let mut tree = MctsBuilder::new().seed(550).verbose(false).build(
+            Parity::initial(),
+            Player::P1,
+            Player::P1,
+        );
+        tree.search_n(3);
+        let visits: Vec<usize> = tree.root.children.iter().map(|c| c.visits).collect();
+        assert_eq!(visits, [1, 1, 1]);
+    }
+
+    #[test]
+    fn an_unvisited_child_is_chosen_first() {
+        for &selection in [Selection::Ucb1, Selection::Ucb1Tuned].iter() {
+            let mut tree = MctsBuilder::new().seed(550).verbose(false).build(
+                Parity::initial(),
+                Player::P1,
+                Player::P1,
+            );
+            tree.config.selection = selection;
+            tree.search_n(3);
+            for i in 0..3 {
+                // Its exploration term divides by zero visits, which must rank it above the
+                // others rather than produce NaN.
+                tree.root.children[i].visits = 0;
+                let state = Parity::initial();
+                let chosen = tree.root.choose_child(Player::P1, &tree.config, &state).unwrap();
+                assert_eq!(chosen.action, Some(i as u8), "{:?}", selection);
+                tree.root.children[i].visits = 1;
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "restricting the root to no moves")]
+    fn restricting_the_root_to_no_moves_panics() {
+        let mut tree = MctsBuilder::new().seed(626).verbose(false).build(
+            Parity::initial(),
+            Player::P1,
+            Player::P1,
+        );
+        tree.restrict_root_moves(0);
+    }
+
+    #[test]
+    fn a_restricted_root_is_only_proven_by_a_win() {
+        let mut tree = MctsBuilder::new().seed(626).verbose(false).build(
+            Parity::initial(),
+            Player::P1,
+            Player::P1,
+        );
+        tree.restrict_root_moves(2);
+        assert_eq!(tree.root.children.len(), 2);
+        assert!(!tree.root.is_solved());
+        // Even if both kept moves were proven losses, the dropped one might not be.
+        let rewards = tree.config.rewards;
+        for child in tree.root.children.iter_mut() {
+            child.solved = Some([rewards.loss, rewards.win]);
         }
+        tree.root.update_solved(Player::P1, &rewards);
+        assert!(!tree.root.is_solved());
+        // A proven win needs nothing from the dropped moves.
+        tree.root.children[1].solved = Some([rewards.win, rewards.loss]);
+        tree.root.update_solved(Player::P1, &rewards);
+        assert!(tree.root.is_solved());
     }
 }
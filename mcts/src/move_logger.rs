@@ -0,0 +1,78 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+
+use rand::Rng;
+
+use {MCTree, State};
+
+/// Appends one JSON object per engine move to a log, for analyzing many games afterwards.
+///
+/// Each record has the fields `move` (counting the moves logged so far), `hash` (from
+/// `State::hash_key`, or `null`), `action`, the root's `visits` and `value`, `pv` (the most
+/// visited line from the root) and `iterations` (the searches spent on this move). Actions are
+/// written in their `Debug` form.
+pub struct MoveLogger<W: Write> {
+    out: W,
+    moves: usize,
+}
+
+impl MoveLogger<File> {
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn create(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(MoveLogger::new(file))
+    }
+}
+
+impl<W: Write> MoveLogger<W> {
+    pub fn new(out: W) -> Self {
+        MoveLogger { out, moves: 0 }
+    }
+    /// Records that `tree` is about to play `action`. Call this after searching and before
+    /// `MCTree::do_action`.
+    pub fn log<S: State, R: Rng>(
+        &mut self,
+        tree: &MCTree<S, R>,
+        action: S::Action,
+    ) -> io::Result<()> {
+        self.moves += 1;
+        let hash = match tree.root_state().hash_key() {
+            Some(hash) => format!("\"{:#x}\"", hash),
+            None => "null".to_string(),
+        };
+        let pv: Vec<String> = tree.root
+            .principal_variation()
+            .iter()
+            .map(|a| json_string(&format!("{:?}", a)))
+            .collect();
+        let fields = [
+            ("move", self.moves.to_string()),
+            ("hash", hash),
+            ("action", json_string(&format!("{:?}", action))),
+            ("visits", tree.root_visits().to_string()),
+            ("value", tree.root_value().to_string()),
+            ("pv", format!("[{}]", pv.join(","))),
+            ("iterations", tree.searches.to_string()),
+        ];
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|&(name, ref value)| format!("\"{}\":{}", name, value))
+            .collect();
+        writeln!(self.out, "{{{}}}", fields.join(","))
+    }
+}
+
+/// `s` as a quoted JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,86 @@
+use rand::Rng;
+
+use solve::score_for;
+use {seeded_rng, MCTree, Outcome, Player, State};
+
+/// A perfect player, such as an external solver, to measure the search against.
+pub trait Oracle<S: State> {
+    /// The result of the unfinished `state` under perfect play, for the player to move: 1 for a
+    /// win, 0 for a draw and -1 for a loss.
+    fn best_value(&self, state: &S) -> i8;
+}
+
+/// How often the engine's moves were optimal, as `oracle_agreement` reports it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OracleReport {
+    /// `plies[i]` is `(optimal, played)`: how many of the moves played at ply `i` kept the best
+    /// result the oracle says the mover could get, out of how many were played.
+    pub plies: Vec<(usize, usize)>,
+}
+
+impl OracleReport {
+    /// The fraction of all moves that were optimal.
+    pub fn agreement(&self) -> f64 {
+        let (optimal, played) = self
+            .plies
+            .iter()
+            .fold((0, 0), |(o, p), &(optimal, played)| (o + optimal, p + played));
+        optimal as f64 / played as f64
+    }
+    fn record(&mut self, ply: usize, optimal: bool) {
+        if self.plies.len() <= ply {
+            self.plies.resize(ply + 1, (0, 0));
+        }
+        self.plies[ply].0 += optimal as usize;
+        self.plies[ply].1 += 1;
+    }
+}
+
+/// Plays `games` games from `state` between two trees that each search `iterations` times a
+/// move, as `self_play` does, and asks `oracle` whether each move was one of the best.
+pub fn oracle_agreement<S: State, O: Oracle<S>, R: Rng>(
+    state: &S,
+    oracle: &O,
+    games: usize,
+    iterations: usize,
+    rng: &mut R,
+) -> OracleReport {
+    let mut report = OracleReport::default();
+    for _ in 0..games {
+        let mut state = state.clone();
+        let mut trees: Vec<_> = [Player::P1, Player::P2]
+            .iter()
+            .map(|&player| {
+                let mut tree =
+                    MCTree::for_player_with_rng(state.clone(), player, seeded_rng(rng.gen()));
+                tree.set_verbose(false);
+                tree
+            })
+            .collect();
+        let mut ply = 0;
+        while let Outcome::Actions(actions) = state.outcome() {
+            let mover = state.next_player();
+            let values: Vec<_> = actions.map(|a| (a, value_after(&state, a, oracle))).collect();
+            let best = values.iter().map(|&(_, value)| value).max().unwrap_or(0);
+            let tree = &mut trees[mover.index()];
+            tree.search_n(iterations);
+            let action = tree.choose_and_do_action();
+            report.record(ply, values.contains(&(action, best)));
+            trees[mover.other().index()].do_action(action);
+            state.do_action(action);
+            ply += 1;
+        }
+    }
+    report
+}
+
+/// The result of playing `action` in `state` under perfect play, for the player making it.
+fn value_after<S: State, O: Oracle<S>>(state: &S, action: S::Action, oracle: &O) -> i8 {
+    let mover = state.next_player();
+    let mut child = state.clone();
+    match child.do_action(action) {
+        Outcome::Actions(_) if child.next_player() == mover => oracle.best_value(&child),
+        Outcome::Actions(_) => -oracle.best_value(&child),
+        outcome => score_for(&outcome, mover),
+    }
+}
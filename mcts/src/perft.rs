@@ -0,0 +1,29 @@
+use {Outcome, State};
+
+/// Counts the games that last exactly `depth` more plies from `state`. Games which end sooner
+/// aren't counted, so comparing these totals against known values checks move generation and
+/// terminal detection together.
+pub fn perft<S: State>(state: &S, depth: usize) -> u64 {
+    match state.outcome() {
+        Outcome::Actions(actions) => count(state, actions, depth),
+        _ if depth == 0 => 1,
+        _ => 0,
+    }
+}
+
+/// `perft` for an unfinished `state` whose legal moves are `actions`.
+fn count<S: State>(state: &S, actions: S::Actions, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    actions
+        .map(|action| {
+            let mut next = state.clone();
+            match next.do_action(action) {
+                Outcome::Actions(actions) => count(&next, actions, depth - 1),
+                _ if depth == 1 => 1,
+                _ => 0,
+            }
+        })
+        .sum()
+}
@@ -0,0 +1,214 @@
+use rand::Rng;
+
+use {seeded_rng, MCTree, Outcome, Player, State, NUM_PLAYERS};
+
+/// Plays `state` out between two trees, one per player, that each search `iterations` times a
+/// move, and returns the final position, the moves that led to it and the result. Seeding `rng`
+/// makes the game reproducible.
+///
+/// Once both trees are confident that the position is a draw, the game stops there and is
+/// recorded as a draw rather than played to the end.
+///
+/// Panics if a tree chooses an illegal move or the game runs past `State::max_playout_moves`,
+/// so playing many games checks the search end to end.
+pub fn self_play<S: State, R: Rng>(
+    state: S,
+    iterations: usize,
+    rng: &mut R,
+) -> (S, Vec<S::Action>, Outcome<()>) {
+    play_match(state, [iterations; NUM_PLAYERS], rng)
+}
+
+/// `self_play` with a separate budget for each player, indexed by `Player::index`.
+pub fn play_match<S: State, R: Rng>(
+    state: S,
+    iterations: [usize; NUM_PLAYERS],
+    rng: &mut R,
+) -> (S, Vec<S::Action>, Outcome<()>) {
+    let trees = [Player::P1, Player::P2]
+        .iter()
+        .map(|&player| {
+            let mut tree = MCTree::for_player_with_rng(state.clone(), player, seeded_rng(rng.gen()));
+            tree.set_verbose(false);
+            tree
+        })
+        .collect();
+    play_out(state, trees, iterations)
+}
+
+/// `play_match` from the root of `opening`, with each player's tree starting as a copy of it,
+/// so that a deeply searched opening can be shared by many games.
+pub fn play_match_from<S: State, G: Rng, R: Rng>(
+    opening: &MCTree<S, G>,
+    iterations: [usize; NUM_PLAYERS],
+    rng: &mut R,
+) -> (S, Vec<S::Action>, Outcome<()>) {
+    let trees = [Player::P1, Player::P2]
+        .iter()
+        .map(|&player| {
+            let mut tree = MCTree::with_prior_tree(opening, player, seeded_rng(rng.gen()));
+            tree.set_verbose(false);
+            tree
+        })
+        .collect();
+    play_out(opening.root_state(), trees, iterations)
+}
+
+/// Plays `state` out between `trees`, indexed by `Player::index`.
+fn play_out<S: State, R: Rng>(
+    mut state: S,
+    mut trees: Vec<MCTree<S, R>>,
+    iterations: [usize; NUM_PLAYERS],
+) -> (S, Vec<S::Action>, Outcome<()>) {
+    let mut moves = Vec::new();
+    while let Outcome::Actions(mut actions) = state.outcome() {
+        assert!(moves.len() < S::max_playout_moves(), "self-play game never ended");
+        let mover = state.next_player();
+        trees[mover.index()].search_n(iterations[mover.index()]);
+        if trees.iter().all(|tree| expects_draw(tree)) {
+            return (state, moves, Outcome::Draw);
+        }
+        let tree = &mut trees[mover.index()];
+        let action = tree.choose_and_do_action();
+        assert!(
+            actions.any(|a| a == action),
+            "{:?} chose the illegal move {:?} in\n{}",
+            mover,
+            action,
+            state
+        );
+        trees[mover.other().index()].do_action(action);
+        state.do_action(action);
+        moves.push(action);
+    }
+    let result = match state.outcome() {
+        Outcome::P1Win => Outcome::P1Win,
+        Outcome::P2Win => Outcome::P2Win,
+        _ => Outcome::Draw,
+    };
+    (state, moves, result)
+}
+
+/// How many playouts a tree's root needs before it can judge the position a draw.
+const DRAW_MIN_VISITS: usize = 1000;
+
+/// How far from a draw's value a root's average reward may be for it to count as a draw.
+const DRAW_MARGIN: f64 = 0.02;
+
+/// The most variance a root's rewards may have for it to count as a draw. A position that is
+/// equal because wins and losses balance out has a high variance, while one where nearly every
+/// playout is drawn has almost none: with rewards of 0, 1/2 and 1, this allows about 5% of
+/// playouts to be decisive.
+const DRAW_VARIANCE: f64 = 0.0125;
+
+/// Whether `tree` is confident that its root is a draw, either because it is proven to be or
+/// because nearly all its playouts were drawn.
+fn expects_draw<S: State, R: Rng>(tree: &MCTree<S, R>) -> bool {
+    let i = tree.perspective.index();
+    let root = &tree.root;
+    let draw = tree.config.rewards.draws[i];
+    let variance = root.squares[i] - root.values[i] * root.values[i];
+    match root.solved {
+        Some(values) => values[i] == draw,
+        None => {
+            root.visits >= DRAW_MIN_VISITS && (root.values[i] - draw).abs() <= DRAW_MARGIN &&
+                variance <= DRAW_VARIANCE
+        }
+    }
+}
+
+/// Wins, draws and losses over a number of games.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Record {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Record {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+    /// The fraction of the points available that were won, counting draws as half a win.
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+    fn add(&mut self, result: Outcome<()>, player: Player) {
+        match (result, player) {
+            (Outcome::Draw, _) => self.draws += 1,
+            (Outcome::P1Win, Player::P1) | (Outcome::P2Win, Player::P2) => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+}
+
+/// The results of `play_series` for the first engine, split by which side it took. The second
+/// engine's results are the same with wins and losses swapped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeriesReport {
+    pub as_first: Record,
+    pub as_second: Record,
+}
+
+impl SeriesReport {
+    /// The first engine's results from both sides together.
+    pub fn total(&self) -> Record {
+        Record {
+            wins: self.as_first.wins + self.as_second.wins,
+            draws: self.as_first.draws + self.as_second.draws,
+            losses: self.as_first.losses + self.as_second.losses,
+        }
+    }
+}
+
+/// Plays `games` games from `state` between two engines searching `iterations[0]` and
+/// `iterations[1]` times a move. The engines swap sides every game, so that with an even number
+/// of games each moves first equally often and a first-move advantage can't skew the total.
+pub fn play_series<S: State, R: Rng>(
+    state: &S,
+    iterations: [usize; 2],
+    games: usize,
+    rng: &mut R,
+) -> SeriesReport {
+    let mut report = SeriesReport::default();
+    for game in 0..games {
+        let (first, record) = if game % 2 == 0 {
+            (Player::P1, &mut report.as_first)
+        } else {
+            (Player::P2, &mut report.as_second)
+        };
+        let mut budgets = [iterations[1]; NUM_PLAYERS];
+        budgets[first.index()] = iterations[0];
+        let (_, _, result) = play_match(state.clone(), budgets, rng);
+        record.add(result, first);
+    }
+    report
+}
+
+/// The most rungs `strength_ladder` climbs.
+const LADDER_RUNGS: usize = 8;
+
+/// `strength_ladder` stops once the engine scores at least this against the baseline.
+const LADDER_TARGET: f64 = 0.75;
+
+/// Plays the engine against a baseline searching `baseline_iterations` times a move, starting at a
+/// quarter of the baseline's budget and doubling it each rung, until it scores `LADDER_TARGET`.
+/// Returns each rung's budget and score, counting draws as half a win, from `play_series`.
+pub fn strength_ladder<S: State, R: Rng>(
+    baseline_iterations: usize,
+    games_per_rung: usize,
+    rng: &mut R,
+) -> Vec<(usize, f64)> {
+    let mut ladder = Vec::new();
+    let mut iterations = (baseline_iterations / 4).max(1);
+    for _ in 0..LADDER_RUNGS {
+        let budgets = [iterations, baseline_iterations];
+        let rate = play_series(&S::initial(), budgets, games_per_rung, rng).total().score();
+        ladder.push((iterations, rate));
+        if rate >= LADDER_TARGET {
+            break;
+        }
+        iterations *= 2;
+    }
+    ladder
+}
@@ -0,0 +1,53 @@
+use {Outcome, Player, State};
+
+/// Proves the outcome of `state` under perfect play by searching at most `depth_limit` plies,
+/// or returns `None` if that isn't enough to decide it.
+///
+/// This is a plain negamax which stops at the first winning move, so it's only practical for
+/// small positions, but it gives ground truth to check the tree search against.
+pub fn solve<S: State>(state: &S, depth_limit: usize) -> Option<Outcome<S::Actions>> {
+    let mover = state.next_player();
+    match state.outcome() {
+        Outcome::Actions(_) => {}
+        outcome => return Some(outcome),
+    }
+    negamax(state, depth_limit).map(|score| match score {
+        1 => Outcome::from_player(mover),
+        -1 => Outcome::from_player(mover.other()),
+        _ => Outcome::Draw,
+    })
+}
+
+/// The score of an unfinished `state` for the player to move: 1 for a win, 0 for a draw and -1
+/// for a loss, if it can be proven within `depth` plies.
+fn negamax<S: State>(state: &S, depth: usize) -> Option<i8> {
+    if depth == 0 {
+        return None;
+    }
+    let mover = state.next_player();
+    let mut best = -1;
+    let mut unknown = false;
+    for action in state.valid_actions(mover) {
+        let mut child = state.clone();
+        let score = match child.do_action(action) {
+            Outcome::Actions(_) => negamax(&child, depth - 1).map(|s| -s),
+            Outcome::Draw => Some(0),
+            outcome => Some(score_for(&outcome, mover)),
+        };
+        match score {
+            Some(1) => return Some(1),
+            Some(s) => best = best.max(s),
+            None => unknown = true,
+        }
+    }
+    // An unproven move might still be a win, so only a proven win settles a partial search.
+    if unknown { None } else { Some(best) }
+}
+
+pub(crate) fn score_for<A: Clone>(outcome: &Outcome<A>, player: Player) -> i8 {
+    match (outcome, player) {
+        (&Outcome::P1Win, Player::P1) | (&Outcome::P2Win, Player::P2) => 1,
+        (&Outcome::P1Win, Player::P2) | (&Outcome::P2Win, Player::P1) => -1,
+        _ => 0,
+    }
+}
@@ -0,0 +1,56 @@
+use std::cell::Cell;
+use std::cmp;
+
+/// Never plan as if fewer than this many moves remain, so that late in a long game the whole
+/// remaining budget isn't spent on a single move.
+const MIN_MOVES_LEFT: usize = 3;
+
+/// Splits a time budget for a whole game across its moves.
+///
+/// Each move gets an even share of the remaining time over the moves expected to remain, which
+/// `MCTree::search_managed` then scales up or down depending on how contested the position is.
+#[derive(Debug)]
+pub struct TimeManager {
+    total_ms: usize,
+    expected_moves: usize,
+    spent_ms: Cell<usize>,
+    moves: Cell<usize>,
+}
+
+impl TimeManager {
+    /// `expected_moves` is how many moves this player expects to make over the whole game.
+    pub fn new(total_ms: usize, expected_moves: usize) -> Self {
+        TimeManager {
+            total_ms,
+            expected_moves,
+            spent_ms: Cell::new(0),
+            moves: Cell::new(0),
+        }
+    }
+    pub fn remaining_ms(&self) -> usize {
+        self.total_ms.saturating_sub(self.spent_ms.get())
+    }
+    /// The even share of the remaining time for the next move. Forced moves get nothing.
+    pub fn base_allocation(&self, legal_actions: usize) -> usize {
+        if legal_actions <= 1 {
+            return 0;
+        }
+        let moves_left = cmp::max(
+            self.expected_moves.saturating_sub(self.moves.get()),
+            MIN_MOVES_LEFT,
+        );
+        self.remaining_ms() / moves_left
+    }
+    /// Scales `base` by how evenly the search is split between root moves: `entropy` is the
+    /// normalized entropy of the root visit distribution, from 0 (one clear move) to 1 (all moves
+    /// equally visited). The result ranges from half to one and a half times `base`.
+    pub fn scale(&self, base: usize, entropy: f64) -> usize {
+        let scaled = (base as f64 * (0.5 + entropy)) as usize;
+        cmp::min(scaled, self.remaining_ms())
+    }
+    /// Records that a move was made after `spent_ms` of searching.
+    pub fn record(&self, spent_ms: usize) {
+        self.spent_ms.set(self.spent_ms.get() + spent_ms);
+        self.moves.set(self.moves.get() + 1);
+    }
+}
@@ -0,0 +1,157 @@
+//! Drives two different games through the same `Box<dyn DynGame>` loop, as a front end that
+//! picks its game at runtime would.
+
+extern crate mcts;
+
+use std::fmt;
+
+use mcts::{seeded_rng, DynGame, Outcome, Player, Session, State};
+
+/// Connect Three on a board 5 columns wide and 4 high, with actions naming the column.
+#[derive(Clone)]
+struct ConnectThree {
+    /// Each column's pieces, from the bottom.
+    columns: Vec<Vec<Player>>,
+    next: Player,
+}
+
+const COLS: usize = 5;
+const ROWS: usize = 4;
+
+impl ConnectThree {
+    fn get(&self, col: i32, row: i32) -> Option<Player> {
+        if col < 0 || row < 0 {
+            return None;
+        }
+        self.columns.get(col as usize).and_then(|c| c.get(row as usize)).cloned()
+    }
+}
+
+impl fmt::Display for ConnectThree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in (0..ROWS as i32).rev() {
+            for col in 0..COLS as i32 {
+                let c = match self.get(col, row) {
+                    Some(Player::P1) => 'X',
+                    Some(Player::P2) => 'O',
+                    None => '.',
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl State for ConnectThree {
+    type Action = u8;
+    type Actions = std::vec::IntoIter<u8>;
+    fn initial() -> Self {
+        ConnectThree { columns: vec![Vec::new(); COLS], next: Player::P1 }
+    }
+    fn do_action(&mut self, col: u8) -> Outcome<Self::Actions> {
+        self.columns[col as usize].push(self.next);
+        self.next = self.next.other();
+        self.outcome()
+    }
+    fn next_player(&self) -> Player {
+        self.next
+    }
+    fn valid_actions(&self, _: Player) -> Self::Actions {
+        if self.has_won(Player::P1) || self.has_won(Player::P2) {
+            return Vec::new().into_iter();
+        }
+        let open = (0..COLS as u8).filter(|&c| self.columns[c as usize].len() < ROWS);
+        open.collect::<Vec<_>>().into_iter()
+    }
+    fn has_won(&self, player: Player) -> bool {
+        let owns = |col: i32, row: i32| self.get(col, row) == Some(player);
+        (0..COLS as i32).any(|col| {
+            (0..ROWS as i32).any(|row| {
+                [(1, 0), (0, 1), (1, 1), (1, -1)]
+                    .iter()
+                    .any(|&(dc, dr)| (0..3).all(|k| owns(col + k * dc, row + k * dr)))
+            })
+        })
+    }
+}
+
+/// Players take 1 to 3 from a pile of 10 in turn, and whoever takes the last one wins.
+#[derive(Clone)]
+struct Nim {
+    pile: u8,
+    next: Player,
+}
+
+impl fmt::Display for Nim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} left", self.pile)
+    }
+}
+
+impl State for Nim {
+    type Action = u8;
+    type Actions = std::vec::IntoIter<u8>;
+    fn initial() -> Self {
+        Nim { pile: 10, next: Player::P1 }
+    }
+    fn do_action(&mut self, take: u8) -> Outcome<Self::Actions> {
+        self.pile -= take;
+        self.next = self.next.other();
+        self.outcome()
+    }
+    fn next_player(&self) -> Player {
+        self.next
+    }
+    fn valid_actions(&self, _: Player) -> Self::Actions {
+        (1..=self.pile.min(3)).collect::<Vec<_>>().into_iter()
+    }
+    fn has_won(&self, player: Player) -> bool {
+        self.pile == 0 && self.next != player
+    }
+}
+
+/// Plays `game` to the end, with the human side always playing the smallest legal move.
+fn play_to_the_end(game: &mut dyn DynGame) -> Outcome<()> {
+    for _ in 0..100 {
+        if let Some(outcome) = game.outcome() {
+            return outcome;
+        }
+        if game.ai_to_move() {
+            let reply = game.ai_move(10);
+            assert!(reply.parse::<u8>().is_ok(), "the AI played `{}`", reply);
+        } else {
+            let played = (0..10).map(|n| n.to_string()).find(|input| game.play(input).is_ok());
+            assert!(played.is_some(), "no legal move in\n{}", game.render());
+        }
+    }
+    panic!("the game didn't end:\n{}", game.render());
+}
+
+#[test]
+fn one_loop_plays_either_game() {
+    let mut games: Vec<Box<dyn DynGame>> = vec![
+        Box::new(Session::new(ConnectThree::initial(), Player::P1, seeded_rng(583))),
+        Box::new(Session::new(Nim::initial(), Player::P2, seeded_rng(583))),
+    ];
+    let outcomes: Vec<Outcome<()>> = games.iter_mut().map(|g| play_to_the_end(&mut **g)).collect();
+    // Taking one at a time from 10 loses to an engine that leaves a multiple of 4.
+    assert!(matches!(outcomes[1], Outcome::P2Win), "{}", games[1].render());
+}
+
+#[test]
+fn moves_are_checked() {
+    let session = Session::new(Nim::initial(), Player::P2, seeded_rng(0));
+    let mut game: Box<dyn DynGame> = Box::new(session);
+    assert_eq!(game.to_move(), Player::P1);
+    assert!(game.play("many").is_err());
+    assert!(game.play("4").is_err());
+    assert_eq!(game.to_move(), Player::P1);
+    assert!(game.play(" 3 ").is_ok());
+    assert!(game.ai_to_move());
+    assert_eq!(game.render(), "7 left");
+    // Even without time to search, the AI has to move.
+    game.ai_move(0);
+    assert_eq!(game.to_move(), Player::P1);
+}
@@ -0,0 +1,108 @@
+//! Plays whole games of tic-tac-toe between two search trees to check the engine end to end:
+//! `self_play` itself panics if a tree ever chooses an illegal move.
+
+extern crate mcts;
+
+use std::fmt;
+
+use mcts::{seeded_rng, self_play, Outcome, Player, State};
+
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+struct TicTacToe {
+    cells: [Option<Player>; 9],
+    next: Player,
+}
+
+impl fmt::Display for TicTacToe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.cells.chunks(3) {
+            for cell in row {
+                let c = match *cell {
+                    Some(Player::P1) => 'X',
+                    Some(Player::P2) => 'O',
+                    None => '.',
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl State for TicTacToe {
+    type Action = usize;
+    type Actions = std::vec::IntoIter<usize>;
+    fn initial() -> Self {
+        TicTacToe { cells: [None; 9], next: Player::P1 }
+    }
+    fn do_action(&mut self, action: usize) -> Outcome<Self::Actions> {
+        assert!(self.cells[action].is_none(), "{} is taken in\n{}", action, self);
+        self.cells[action] = Some(self.next);
+        self.next = self.next.other();
+        self.outcome()
+    }
+    fn next_player(&self) -> Player {
+        self.next
+    }
+    fn valid_actions(&self, _: Player) -> Self::Actions {
+        if self.has_won(Player::P1) || self.has_won(Player::P2) {
+            return Vec::new().into_iter();
+        }
+        let blanks: Vec<usize> = (0..9).filter(|&i| self.cells[i].is_none()).collect();
+        blanks.into_iter()
+    }
+    fn has_won(&self, player: Player) -> bool {
+        LINES.iter().any(|line| line.iter().all(|&i| self.cells[i] == Some(player)))
+    }
+}
+
+#[test]
+fn self_play_games_end_coherently() {
+    for seed in 0..20 {
+        let (state, moves, result) = self_play(TicTacToe::initial(), 200, &mut seeded_rng(seed));
+        // The moves lead from the start to the final position.
+        let mut replayed = TicTacToe::initial();
+        for &action in &moves {
+            replayed.do_action(action);
+        }
+        assert_eq!(replayed, state, "seed {}", seed);
+        let (x_won, o_won) = (state.has_won(Player::P1), state.has_won(Player::P2));
+        match result {
+            Outcome::P1Win => assert!(x_won && !o_won, "seed {}:\n{}", seed, state),
+            Outcome::P2Win => assert!(o_won && !x_won, "seed {}:\n{}", seed, state),
+            // A draw is either played out or adjudicated before the board fills.
+            Outcome::Draw => assert!(!x_won && !o_won, "seed {}:\n{}", seed, state),
+            Outcome::Actions(_) => panic!("seed {}: self_play returned an unfinished game", seed),
+        }
+    }
+}
+
+#[test]
+fn self_play_is_reproducible() {
+    for seed in 0..5 {
+        let (_, first, _) = self_play(TicTacToe::initial(), 100, &mut seeded_rng(seed));
+        let (_, second, _) = self_play(TicTacToe::initial(), 100, &mut seeded_rng(seed));
+        assert_eq!(first, second, "seed {}", seed);
+    }
+}
+
+/// Tic-tac-toe is a draw with perfect play, which a reasonable search should find.
+#[test]
+fn strong_self_play_draws() {
+    for seed in 0..5 {
+        let (state, _, result) = self_play(TicTacToe::initial(), 2000, &mut seeded_rng(seed));
+        assert!(matches!(result, Outcome::Draw), "seed {}:\n{}", seed, state);
+    }
+}
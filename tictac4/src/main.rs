@@ -1,9 +1,17 @@
 extern crate mcts;
+extern crate rand;
 
 use std::fmt;
+use std::fs;
 use std::io;
+use std::io::BufRead;
 use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
 use mcts::*;
+use rand::Rng;
 
 use std::str::FromStr;
 
@@ -35,6 +43,41 @@ impl T4Cell {
             Player::P2 => T4Cell::O,
         }
     }
+
+    /// The two-bit code of the cell in `T4Board::to_bytes`.
+    fn code(self) -> u8 {
+        match self {
+            T4Cell::Blank => 0,
+            T4Cell::X => 1,
+            T4Cell::O => 2,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<T4Cell> {
+        match code {
+            0 => Some(T4Cell::Blank),
+            1 => Some(T4Cell::X),
+            2 => Some(T4Cell::O),
+            _ => None,
+        }
+    }
+}
+
+/// The rows, columns and diagonals of a 3x3 grid, as cell indices.
+const WINNING_LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// Whether `owned` holds for every cell of some line.
+fn any_line<F: Fn(usize) -> bool>(lines: &[[usize; 3]], owned: F) -> bool {
+    lines.iter().any(|line| line.iter().all(|&i| owned(i)))
 }
 
 #[derive(Clone)]
@@ -82,39 +125,34 @@ impl T2Board {
         }
     }
 
-    fn blanks(&self) -> Vec<u8> {
+    /// The blank cells, as a bitmask with bit `i` for cell `i`.
+    fn blanks(&self) -> u16 {
         (0..9)
-            .filter(|i| self.cells[*i as usize] == T4Cell::Blank)
-            .collect()
+            .filter(|&i| self.cells[i] == T4Cell::Blank)
+            .fold(0, |mask, i| mask | 1 << i)
     }
 
     fn has_won_p(&self, player: Player) -> bool {
         let p = T4Cell::from_player(player);
-        if self.cells[0] == p && self.cells[1] == p && self.cells[2] == p {
-            return true;
-        }
-        if self.cells[3] == p && self.cells[4] == p && self.cells[5] == p {
-            return true;
-        }
-        if self.cells[6] == p && self.cells[7] == p && self.cells[8] == p {
-            return true;
-        }
-        if self.cells[0] == p && self.cells[3] == p && self.cells[6] == p {
-            return true;
-        }
-        if self.cells[1] == p && self.cells[4] == p && self.cells[7] == p {
-            return true;
-        }
-        if self.cells[2] == p && self.cells[5] == p && self.cells[8] == p {
-            return true;
-        }
-        if self.cells[0] == p && self.cells[4] == p && self.cells[8] == p {
-            return true;
-        }
-        if self.cells[2] == p && self.cells[4] == p && self.cells[6] == p {
-            return true;
+        any_line(&WINNING_LINES, |i| self.cells[i] == p)
+    }
+
+    /// The result of this board with perfect play and `to_move` moving next, as if it were a game
+    /// of its own: 1 if `to_move` wins, 0 for a draw and -1 if they lose.
+    #[allow(dead_code)]
+    fn solve(&self, to_move: Player) -> i8 {
+        if self.winning_piece != T4Cell::Blank {
+            return if self.winning_piece == T4Cell::from_player(to_move) { 1 } else { -1 };
         }
-        false
+        (0..9)
+            .filter(|&place| self.valid(place))
+            .map(|place| {
+                let mut board = self.clone();
+                board.play(place, to_move);
+                -board.solve(to_move.other())
+            })
+            .max()
+            .unwrap_or(0)
     }
 }
 
@@ -136,6 +174,28 @@ impl T4Move {
     fn new(macro_: u8, micro: u8) -> Self {
         T4Move { macro_, micro }
     }
+
+    /// The move spelled out for players, e.g. "board 4, cell 0 (4.0)".
+    fn describe(&self) -> String {
+        format!("board {}, cell {} ({})", self.macro_, self.micro, self)
+    }
+}
+
+impl fmt::Display for T4Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.macro_, self.micro)
+    }
+}
+
+/// Parses `macro.micro`, e.g. `4.0` for the top left cell of the center board.
+impl FromStr for T4Move {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut parts = s.splitn(2, '.');
+        let macro_ = parts.next().and_then(|p| u8::from_str(p).ok()).ok_or(())?;
+        let micro = parts.next().and_then(|p| u8::from_str(p).ok()).ok_or(())?;
+        Ok(T4Move::new(macro_, micro))
+    }
 }
 
 impl T4Board {
@@ -191,48 +251,81 @@ impl T4Board {
     }
 
     fn has_won_p(&self, player: Player) -> bool {
+        self.winning_macro_line(player).is_some()
+    }
+
+    /// The three sub-boards won by `player` in a row, if there are any.
+    fn winning_macro_line(&self, player: Player) -> Option<[u8; 3]> {
         let p = T4Cell::from_player(player);
-        if self.boards[0].winning_piece == p && self.boards[1].winning_piece == p
-            && self.boards[2].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[3].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[5].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[6].winning_piece == p && self.boards[7].winning_piece == p
-            && self.boards[8].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[0].winning_piece == p && self.boards[3].winning_piece == p
-            && self.boards[6].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[1].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[7].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[2].winning_piece == p && self.boards[5].winning_piece == p
-            && self.boards[8].winning_piece == p
-        {
-            return true;
+        WINNING_LINES
+            .iter()
+            .find(|line| line.iter().all(|&i| self.boards[i].winning_piece == p))
+            .map(|line| [line[0] as u8, line[1] as u8, line[2] as u8])
+    }
+
+    /// A fixed-size encoding for sending positions over the wire. The 81 cells, board by board,
+    /// then each board's winner are packed as two-bit `T4Cell` codes, four to a byte; the last
+    /// byte holds the player to move in its high nibble and the forced board, or 9 for any, in
+    /// its low one.
+    #[allow(dead_code)]
+    fn to_bytes(&self) -> [u8; 24] {
+        let codes = self
+            .boards
+            .iter()
+            .flat_map(|b| b.cells.iter())
+            .chain(self.boards.iter().map(|b| &b.winning_piece))
+            .map(|c| c.code());
+        let mut bytes = [0; 24];
+        for (i, code) in codes.enumerate() {
+            bytes[i / 4] |= code << (2 * (i % 4));
         }
-        if self.boards[0].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[8].winning_piece == p
-        {
-            return true;
+        bytes[23] = (self.next_player.index() as u8) << 4 | self.next_board.unwrap_or(9);
+        bytes
+    }
+
+    /// Decodes `to_bytes`, rejecting anything that isn't a position from a game.
+    #[allow(dead_code)]
+    fn from_bytes(bytes: &[u8; 24]) -> Result<T4Board, String> {
+        let code = |i: usize| (bytes[i / 4] >> (2 * (i % 4))) & 0b11;
+        let cell = |i: usize| T4Cell::from_code(code(i)).ok_or_else(|| format!("Bad cell {}", i));
+        let mut board = T4Board::new();
+        for (b, sub) in board.boards.iter_mut().enumerate() {
+            for (c, cell_ref) in sub.cells.iter_mut().enumerate() {
+                *cell_ref = cell(b * 9 + c)?;
+            }
+            sub.winning_piece = cell(81 + b)?;
+            let valid_winner = match sub.winning_piece {
+                T4Cell::Blank => !sub.has_won_p(Player::P1) && !sub.has_won_p(Player::P2),
+                T4Cell::X => sub.has_won_p(Player::P1),
+                T4Cell::O => sub.has_won_p(Player::P2),
+            };
+            if !valid_winner {
+                return Err(format!("Board {} has the wrong winner", b));
+            }
         }
-        if self.boards[2].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[6].winning_piece == p
-        {
-            return true;
+        let cells = || board.boards.iter().flat_map(|b| b.cells.iter());
+        let count = |p| cells().filter(|&&c| c == p).count();
+        let (xs, os) = (count(T4Cell::X), count(T4Cell::O));
+        board.next_player = match xs.checked_sub(os) {
+            Some(0) => Player::P1,
+            Some(1) => Player::P2,
+            _ => return Err(format!("{} Xs and {} Os can't happen in a game", xs, os)),
+        };
+        if (bytes[23] >> 4) as usize != board.next_player.index() {
+            return Err(format!("{:?} can't be to move here", bytes[23] >> 4));
         }
-        false
+        board.next_board = match bytes[23] & 0xf {
+            9 => None,
+            b if b < 9 && !board.boards[b as usize].full() => Some(b),
+            b => return Err(format!("Board {} can't be the next one", b)),
+        };
+        board.winner = match (board.has_won_p(Player::P1), board.has_won_p(Player::P2)) {
+            (false, false) => T4Cell::Blank,
+            (true, false) => T4Cell::X,
+            (false, true) => T4Cell::O,
+            (true, true) => return Err("Both players have won".to_string()),
+        };
+        Ok(board)
     }
 }
 
@@ -244,7 +337,7 @@ impl fmt::Display for T4Board {
                     write!(
                         f,
                         "{}",
-                        self.boards[3 * macro_row + macro_col].cells[3 * micro_row + 0]
+                        self.boards[3 * macro_row + macro_col].cells[3 * micro_row]
                     )?;
                     write!(
                         f,
@@ -261,14 +354,14 @@ impl fmt::Display for T4Board {
                     }
                 }
                 if *macro_row == 1 {
-                    write!(f, "     {}", self.boards[3 * micro_row + 0].winning_piece)?;
+                    write!(f, "     {}", self.boards[3 * micro_row].winning_piece)?;
                     write!(f, "{}", self.boards[3 * micro_row + 1].winning_piece)?;
                     write!(f, "{}", self.boards[3 * micro_row + 2].winning_piece)?;
                 }
-                writeln!(f, "")?;
+                writeln!(f)?;
             }
             if *macro_row == 2 {
-                writeln!(f, "")?;
+                writeln!(f)?;
             } else {
                 writeln!(f, "----+-----+----")?;
             }
@@ -277,31 +370,30 @@ impl fmt::Display for T4Board {
     }
 }
 
-#[derive(Debug, Clone)]
+/// The legal moves, with bit `9 * macro + micro` set for each.
+#[derive(Debug, Clone, Default)]
 struct T4BoardIter {
-    moves: std::vec::IntoIter<T4Move>,
+    bitvec: u128,
 }
 
 impl Iterator for T4BoardIter {
     type Item = T4Move;
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.moves.size_hint()
+        let ones = self.bitvec.count_ones() as usize;
+        (ones, Some(ones))
     }
     fn next(&mut self) -> Option<Self::Item> {
-        self.moves.next()
+        if self.bitvec == 0 {
+            return None;
+        }
+        let bit = self.bitvec.trailing_zeros() as u8;
+        self.bitvec &= self.bitvec - 1;
+        Some(T4Move::new(bit / 9, bit % 9))
     }
 }
 
 impl ExactSizeIterator for T4BoardIter {}
 
-impl Default for T4BoardIter {
-    fn default() -> Self {
-        T4BoardIter {
-            moves: Vec::new().into_iter(),
-        }
-    }
-}
-
 impl State for T4Board {
     type Action = T4Move;
     type Actions = T4BoardIter;
@@ -327,155 +419,617 @@ impl State for T4Board {
     }
 
     fn valid_actions(&self, _: Player) -> Self::Actions {
-        let v: Vec<T4Move> = if let Some(macro_) = self.next_board {
-            self.boards[macro_ as usize]
-                .blanks()
-                .into_iter()
-                .map(|micro| T4Move::new(macro_, micro))
-                .collect()
-        } else {
-            (0..9)
-                .flat_map(|macro_| {
-                    self.boards[macro_]
-                        .blanks()
-                        .into_iter()
-                        .map(move |micro| T4Move::new(macro_ as u8, micro))
-                })
-                .collect()
+        let bitvec = match self.next_board {
+            Some(macro_) => u128::from(self.boards[macro_ as usize].blanks()) << (9 * macro_),
+            None => (0..9).fold(0, |bitvec, macro_| {
+                bitvec | u128::from(self.boards[macro_].blanks()) << (9 * macro_)
+            }),
         };
-        T4BoardIter {
-            moves: v.into_iter(),
-        }
+        T4BoardIter { bitvec }
     }
 
     fn has_won(&self, player: Player) -> bool {
-        let p = T4Cell::from_player(player);
-        if self.boards[0].winning_piece == p && self.boards[1].winning_piece == p
-            && self.boards[2].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[3].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[5].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[6].winning_piece == p && self.boards[7].winning_piece == p
-            && self.boards[8].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[0].winning_piece == p && self.boards[3].winning_piece == p
-            && self.boards[6].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[1].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[7].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[2].winning_piece == p && self.boards[5].winning_piece == p
-            && self.boards[8].winning_piece == p
-        {
-            return true;
-        }
-        if self.boards[0].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[8].winning_piece == p
-        {
-            return true;
+        self.has_won_p(player)
+    }
+
+    /// Every move fills one of the 81 cells, so no game outlasts the board.
+    fn max_playout_moves() -> usize {
+        81
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `games` games of random moves, seeded with `seed`, calling `check` after each move.
+    fn play_random_games<F: FnMut(&T4Board)>(games: usize, seed: u64, mut check: F) {
+        let mut rng = seeded_rng(seed);
+        for _ in 0..games {
+            let mut board = T4Board::initial();
+            while let Outcome::Actions(actions) = board.outcome() {
+                let action = board.rollout_action(actions, &mut rng);
+                board.do_action(action);
+                check(&board);
+            }
         }
-        if self.boards[2].winning_piece == p && self.boards[4].winning_piece == p
-            && self.boards[6].winning_piece == p
-        {
-            return true;
+    }
+
+    #[test]
+    fn has_won_agrees_with_has_won_p_on_the_macro_board() {
+        let mut wins = 0;
+        play_random_games(500, 564, |board| {
+            // The sub-boards' winners, checked as a tic-tac-toe board of their own.
+            let mut macro_board = T2Board::new();
+            for (cell, sub) in macro_board.cells.iter_mut().zip(board.boards.iter()) {
+                *cell = sub.winning_piece;
+            }
+            for &player in [Player::P1, Player::P2].iter() {
+                let won = board.has_won(player);
+                assert_eq!(won, board.has_won_p(player), "\n{}", board);
+                assert_eq!(won, macro_board.has_won_p(player), "\n{}", board);
+                assert_eq!(won, board.winner == T4Cell::from_player(player));
+                wins += won as usize;
+            }
+        });
+        assert!(wins > 0);
+    }
+
+    /// A board with `moves` played in turn, X first.
+    fn t2_board(moves: &[u8]) -> T2Board {
+        let mut board = T2Board::new();
+        for (i, &place) in moves.iter().enumerate() {
+            let player = if i.is_multiple_of(2) { Player::P1 } else { Player::P2 };
+            assert!(board.play(place, player));
         }
-        false
+        board
+    }
+
+    #[test]
+    fn tic_tac_toe_is_a_draw() {
+        assert_eq!(T2Board::new().solve(Player::P1), 0);
+        assert_eq!(T2Board::new().solve(Player::P2), 0);
+    }
+
+    #[test]
+    fn solve_finds_forced_results() {
+        // Cells are numbered 0 to 8 from the top left, so 4 is the center and 1 an edge.
+        assert_eq!(t2_board(&[4, 1]).solve(Player::P1), 1);
+        assert_eq!(t2_board(&[4, 0]).solve(Player::P1), 0);
+        assert_eq!(t2_board(&[0, 1]).solve(Player::P1), 1);
+        assert_eq!(t2_board(&[0, 4]).solve(Player::P1), 0);
+        // X has forked, threatening both 5 and 6.
+        assert_eq!(t2_board(&[0, 1, 4, 8, 3]).solve(Player::P2), -1);
+    }
+
+    #[test]
+    fn solve_scores_finished_boards() {
+        let won = t2_board(&[0, 3, 1, 4, 2]);
+        assert_eq!(won.solve(Player::P1), 1);
+        assert_eq!(won.solve(Player::P2), -1);
+        let drawn = t2_board(&[0, 4, 8, 1, 7, 6, 2, 5, 3]);
+        assert!(drawn.full() && drawn.winning_piece == T4Cell::Blank);
+        assert_eq!(drawn.solve(Player::P2), 0);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        play_random_games(200, 606, |board| {
+            let bytes = board.to_bytes();
+            let decoded = T4Board::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.to_bytes(), bytes, "\n{}", board);
+            assert_eq!(decoded.next_player, board.next_player);
+            assert_eq!(decoded.next_board, board.next_board);
+            assert_eq!(decoded.winner, board.winner);
+            let moves = |b: &T4Board| b.valid_actions(b.next_player).collect::<Vec<_>>();
+            assert_eq!(moves(&decoded), moves(board));
+        });
+    }
+
+    #[test]
+    fn from_bytes_rejects_impossible_positions() {
+        // Sets the code of cell `i`, counting the sub-boards' winners as cells 81 to 89.
+        let set = |bytes: &mut [u8; 24], i: usize, cell: T4Cell| {
+            bytes[i / 4] &= !(0b11 << (2 * (i % 4)));
+            bytes[i / 4] |= cell.code() << (2 * (i % 4));
+        };
+        let empty = T4Board::initial().to_bytes();
+        assert!(T4Board::from_bytes(&empty).is_ok());
+
+        let mut bytes = empty;
+        set(&mut bytes, 0, T4Cell::X);
+        bytes[23] = 1 << 4 | 9;
+        assert!(T4Board::from_bytes(&bytes).is_ok());
+        // X has moved, so it's O's turn.
+        bytes[23] = 9;
+        assert!(T4Board::from_bytes(&bytes).is_err());
+        // There are only nine boards.
+        bytes[23] = 1 << 4 | 10;
+        assert!(T4Board::from_bytes(&bytes).is_err());
+        // Two Xs and no Os.
+        bytes[23] = 1 << 4 | 9;
+        set(&mut bytes, 1, T4Cell::X);
+        assert!(T4Board::from_bytes(&bytes).is_err());
+
+        // A code that isn't a cell.
+        let mut bytes = empty;
+        bytes[0] = 0b11;
+        assert!(T4Board::from_bytes(&bytes).is_err());
+        // A board won by X without any of X's pieces on it.
+        let mut bytes = empty;
+        set(&mut bytes, 81, T4Cell::X);
+        assert!(T4Board::from_bytes(&bytes).is_err());
+    }
+}
+
+/// Why a line of input isn't a board number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseMoveError {
+    NotANumber,
+    /// A number, but not one from 0 to 8.
+    OutOfRange,
+    /// The player typed `quit` instead.
+    Quit,
+}
+
+/// Parses the number, from 0 to 8, of a macro or micro board.
+fn parse_cell(token: &str) -> Result<u8, ParseMoveError> {
+    let token = token.trim();
+    if token == "quit" {
+        return Err(ParseMoveError::Quit);
+    }
+    // `u8::from_str` would also take a leading `+`.
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ParseMoveError::NotANumber);
+    }
+    match u8::from_str(token) {
+        Ok(cell) if cell < 9 => Ok(cell),
+        _ => Err(ParseMoveError::OutOfRange),
     }
 }
 
-fn get_move(s: &T4Board) -> T4Move {
+/// Prompts for a line of `input`, returning `None` at end of input or if it can't be read.
+fn read_line<I: BufRead>(prompt: &str, input: &mut I) -> Option<String> {
     let mut line = String::new();
-    fn parse(line: &str) -> u8 {
-        match line.trim() {
-            "0" => 0,
-            "1" => 1,
-            "2" => 2,
-            "3" => 3,
-            "4" => 4,
-            "5" => 5,
-            "6" => 6,
-            "7" => 7,
-            "8" => 8,
-            _ => 9,
+    println!("{}", prompt);
+    match input.read_line(&mut line) {
+        Ok(0) => None,
+        Ok(_) => Some(line),
+        Err(e) => {
+            eprintln!("Could not read input: {}", e);
+            None
         }
     }
+}
+
+enum Command {
+    Play(T4Move),
+    /// Save the game to the given file.
+    Save(String),
+    Quit,
+}
+
+/// Prompts until `input` gives a valid move or a command. Returns `None` at end of input.
+fn get_command<I: BufRead>(s: &T4Board, input: &mut I) -> Option<Command> {
     loop {
-        println!("enter a macro board: ");
-        io::stdin().read_line(&mut line).unwrap();
-        let macro_ = parse(line.as_str());
-        line.clear();
-        println!("enter a micro board: ");
-        io::stdin().read_line(&mut line).unwrap();
-        let micro = parse(line.as_str());
-        line.clear();
-        let m = T4Move::new(macro_, micro);
-        if !s.valid(m) {
-            println!("Invalid move!");
-        } else {
-            return m;
+        let line = read_line("enter a macro board (or `save <file>` or `quit`): ", input)?;
+        if let Some(path) = line.trim().strip_prefix("save ") {
+            return Some(Command::Save(path.trim().to_string()));
+        }
+        // A bad macro board is reported straight away, without asking for the micro board.
+        let cells = match parse_cell(&line) {
+            Ok(macro_) => {
+                let micro = parse_cell(&read_line("enter a micro board: ", input)?);
+                micro.map(|micro| (macro_, micro))
+            }
+            Err(e) => Err(e),
+        };
+        match cells {
+            Ok((macro_, micro)) if s.valid(T4Move::new(macro_, micro)) => {
+                return Some(Command::Play(T4Move::new(macro_, micro)))
+            }
+            Ok(_) => println!("That cell can't be played!"),
+            Err(ParseMoveError::NotANumber) => println!("That's not a board number!"),
+            Err(ParseMoveError::OutOfRange) => println!("Boards are numbered 0 to 8!"),
+            Err(ParseMoveError::Quit) => return Some(Command::Quit),
         }
     }
 }
 
-#[allow(dead_code)]
-fn mcts(thinking_time: usize) {
+/// Writes the moves so far, in the form `--moves` takes, followed by the board.
+fn save(path: &str, moves: &[T4Move], board: &T4Board) -> io::Result<()> {
+    let transcript: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+    fs::write(path, format!("{}\n{}\n", transcript.join(","), board))
+}
+
+/// How many hopeless positions in a row it takes for the AI to resign.
+const RESIGN_MOVES: usize = 3;
+
+/// How many moves the AI expects to make in a typical game, for splitting `--game-time`.
+const EXPECTED_MOVES: usize = 30;
+
+const USAGE: &str = "Usage: tictac4 [thinking_ms] [options]
+    --ai-first              let the AI make the first move
+    --moves <4.0,0.4>       start from the position after these moves
+    --iterations <n>        search a fixed number of iterations per move
+    --game-time <ms>        split a time budget across the whole game
+    --seed <u64>            seed the AI's rng, for reproducible games
+    --ponder                keep searching while waiting for your move
+    --log <file>            append a JSON line per AI move to the file
+    --quiet, -q             print only the AI's moves and the board
+    --verbose, -v, -vv      explain each AI move
+    --select <value|visits> play the best valued (default) or most visited move
+    --handicap <p>          have the AI value a draw at p, from 0 (a loss) to 1 (a win)
+    --resign <p>            resign once win prob stays below p (default 0.02)
+    --claim <p>             announce a win once win prob exceeds p (default 0.98)
+    --bench <iterations>    benchmark search on a fixed position and exit";
+
+struct Options {
+    thinking_time: usize,
+    iterations: Option<usize>,
+    seed: Option<u64>,
+    time_manager: Option<TimeManager>,
+    bench: Option<usize>,
+    ai_first: bool,
+    /// Whether the AI searches while the human thinks.
+    ponder: bool,
+    /// Where to log the AI's decisions.
+    log: Option<String>,
+    verbosity: Verbosity,
+    move_selection: MoveSelection,
+    /// What a draw is worth to the AI, if not half a win.
+    handicap: Option<f64>,
+    /// The AI resigns after `RESIGN_MOVES` moves in a row valued below this.
+    resign_threshold: f64,
+    /// The AI announces its expected win once its value exceeds this.
+    claim_threshold: f64,
+    moves: Vec<T4Move>,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            thinking_time: 3000,
+            iterations: None,
+            seed: None,
+            time_manager: None,
+            bench: None,
+            ai_first: false,
+            ponder: false,
+            log: None,
+            verbosity: Verbosity::Normal,
+            move_selection: MoveSelection::Value,
+            handicap: None,
+            resign_threshold: 0.02,
+            claim_threshold: 0.98,
+            moves: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = Some(flag_value(&arg, args.next())?),
+                "--iterations" => {
+                    let iterations = flag_value(&arg, args.next())?;
+                    if iterations == 0 {
+                        return Err("--iterations must be at least 1".to_string());
+                    }
+                    options.iterations = Some(iterations);
+                }
+                "--ai-first" => options.ai_first = true,
+                "--ponder" => options.ponder = true,
+                "--quiet" | "-q" => options.verbosity = Verbosity::Silent,
+                "--verbose" | "-v" | "-vv" => options.verbosity = Verbosity::Debug,
+                "--select" => options.move_selection = flag_value(&arg, args.next())?,
+                "--handicap" => options.handicap = Some(flag_value(&arg, args.next())?),
+                "--log" => options.log = Some(flag_value(&arg, args.next())?),
+                "--resign" => options.resign_threshold = flag_value(&arg, args.next())?,
+                "--claim" => options.claim_threshold = flag_value(&arg, args.next())?,
+                "--moves" => options.moves = parse_moves(&flag_value::<String>(&arg, args.next())?)?,
+                "--bench" => options.bench = Some(flag_value(&arg, args.next())?),
+                "--game-time" => {
+                    let total = flag_value(&arg, args.next())?;
+                    options.time_manager = Some(TimeManager::new(total, EXPECTED_MOVES));
+                }
+                _ => {
+                    options.thinking_time = usize::from_str(&arg)
+                        .map_err(|_| format!("Unrecognized argument `{}`", arg))?
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Parses a comma-separated move transcript.
+fn parse_moves<T: FromStr>(transcript: &str) -> Result<Vec<T>, String> {
+    transcript
+        .split(',')
+        .enumerate()
+        .map(|(i, m)| {
+            T::from_str(m.trim()).map_err(|_| format!("Invalid move `{}` at index {}", m, i))
+        })
+        .collect()
+}
+
+/// Plays `moves` from the initial position, failing on the first illegal one.
+fn replay(moves: &[T4Move]) -> Result<T4Board, String> {
     let mut board = T4Board::initial();
-    let mut mctree = MCTree::new(board.clone(), Player::P2, Player::P1);
-    mctree.search_for(thinking_time);
-    println!("{}", board);
-    loop {
-        let user_col = get_move(&board);
-        board.do_action(user_col);
-        if board.has_won(Player::P1) {
-            println!("X Won!");
-            break;
+    for (i, &m) in moves.iter().enumerate() {
+        if !board.valid_actions(board.next_player()).any(|a| a == m) {
+            return Err(format!("Illegal move {} at index {}", m, i));
         }
-        println!("{}", board);
-        mctree.do_action(user_col);
-        mctree.search_for(thinking_time);
-        let ai_col = mctree.choose_and_do_action();
-        board.do_action(ai_col);
-        println!("The AI played move {:?}", ai_col);
-        println!(
-            " it has played {} games from this position",
-            mctree.root.visits()
-        );
-        println!(
-            " and it believes it will win with p = {}",
-            mctree.root.value()
-        );
+        board.do_action(m);
+    }
+    Ok(board)
+}
+
+fn flag_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    T::from_str(&value).map_err(|_| format!("Invalid value `{}` for {}", value, flag))
+}
+
+fn search<R: Rng>(mctree: &mut MCTree<T4Board, R>, options: &Options) {
+    match (options.iterations, &options.time_manager) {
+        (Some(iterations), _) => mctree.search_n(iterations),
+        (None, Some(tm)) => mctree.search_managed(tm),
+        (None, None) => mctree.search_for(options.thinking_time),
+    };
+}
+
+/// Lists every root move with the line the AI expects to follow it, then the raw statistics of
+/// the root and its children.
+fn print_reasoning<R: Rng>(mctree: &MCTree<T4Board, R>) {
+    for analysis in mctree.top_moves(usize::MAX) {
+        let pv: Vec<String> = analysis.pv.iter().map(|m| m.to_string()).collect();
         println!(
-            " it has explored {} moves ahead fully, and has ventured as far as {} moves",
-            mctree.root.min_depth(),
-            mctree.root.max_depth()
+            "move {}: {} visits, win prob {:.2}, expecting {}",
+            analysis.action,
+            analysis.visits,
+            analysis.value,
+            pv.join(" ")
         );
+    }
+    mctree.root.print_1_layer();
+}
+
+/// Says how much of its search the AI kept after the human's move.
+fn print_reroot(reroot: Reroot) {
+    match reroot {
+        Reroot::Preserved { subtree_visits } => {
+            println!("The AI reused {} playouts it had made after that move", subtree_visits)
+        }
+        Reroot::Rebuilt => println!("The AI hadn't looked at that move and starts fresh"),
+    }
+}
+
+fn print_diagnostics<R: Rng>(mctree: &MCTree<T4Board, R>) {
+    println!(
+        " it has played {} games from this position",
+        mctree.root_visits()
+    );
+    let (low, high) = mctree.root.value_confidence();
+    println!(
+        " and it believes it will win with p = {:.2} ± {:.2}",
+        mctree.root_value(),
+        (high - low) / 2.0
+    );
+    println!(
+        " it has explored {} moves ahead fully, and has ventured as far as {} moves",
+        mctree.min_depth(),
+        mctree.max_depth()
+    );
+}
+
+fn print_evaluations<R: Rng>(board: &T4Board, mctree: &MCTree<T4Board, R>) {
+    let total = mctree.root_visits() as f64;
+    let annotations: Vec<(T4Move, String)> = mctree
+        .root_evaluations()
+        .into_iter()
+        .take(5)
+        .map(|(m, visits, value)| {
+            let label = format!(
+                "{:.0}% visits, win prob {:.2}",
+                100.0 * visits as f64 / total,
+                value
+            );
+            (m, label)
+        })
+        .collect();
+    println!("{}", board.render_with_annotations(&annotations));
+}
+
+/// Waits for the human's command while the AI keeps searching the current position.
+fn ponder<R: Rng, I: BufRead + Send>(
+    mctree: &mut MCTree<T4Board, R>,
+    board: &T4Board,
+    input: &mut I,
+) -> Option<Command> {
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let input = get_command(board, input);
+            stop.store(true, Ordering::Relaxed);
+            input
+        });
+        mctree.search_with_stop(&stop);
+        reader.join().unwrap()
+    })
+}
+
+/// How the end of a game is announced.
+fn result_message<A: Clone>(outcome: &Outcome<A>) -> &'static str {
+    match *outcome {
+        Outcome::P1Win => "X Won!",
+        Outcome::P2Win => "O Won!",
+        _ => "Draw",
+    }
+}
+
+fn mcts<R: Rng, I: BufRead + Send>(options: &Options, mut board: T4Board, rng: R, input: &mut I) {
+    let ai = if options.ai_first { Player::P1 } else { Player::P2 };
+    let mut mctree = MCTree::for_player_with_rng(board.clone(), ai, rng);
+    mctree.set_move_selection(options.move_selection);
+    if let Some(draw) = options.handicap {
+        mctree.set_rewards(RewardScheme::default().handicapped(ai, draw));
+    }
+    mctree.set_verbosity(options.verbosity);
+    if let Some(outcome) = mctree.is_terminal() {
         println!("{}", board);
-        if board.has_won(Player::P2) {
-            println!("O Won!");
-            break;
+        println!("The game is already over: {}", result_message(&outcome));
+        return;
+    }
+    if board.next_player() != ai {
+        search(&mut mctree, options);
+    }
+    let mut moves = options.moves.clone();
+    let mut logger = options.log.as_ref().map(|path| {
+        MoveLogger::create(path).unwrap_or_else(|e| {
+            eprintln!("Could not open {}: {}", path, e);
+            process::exit(1)
+        })
+    });
+    let mut hopeless_moves = 0;
+    let mut claimed = false;
+    println!("{}", board);
+    loop {
+        let ai_to_move = board.next_player() == ai;
+        if ai_to_move {
+            search(&mut mctree, options);
+            if mctree.root_value() < options.resign_threshold {
+                hopeless_moves += 1;
+                if hopeless_moves >= RESIGN_MOVES {
+                    println!("AI resigns");
+                    break;
+                }
+            } else {
+                hopeless_moves = 0;
+            }
+            if options.verbosity >= Verbosity::Normal {
+                print_evaluations(&board, &mctree);
+            }
+            if options.verbosity >= Verbosity::Debug {
+                print_reasoning(&mctree);
+            }
+            let ai_col = mctree.best_action().unwrap();
+            if let Some(logger) = logger.as_mut() {
+                if let Err(e) = logger.log(&mctree, ai_col) {
+                    eprintln!("Could not write to the log: {}", e);
+                }
+            }
+            mctree.do_action(ai_col);
+            board.do_action(ai_col);
+            moves.push(ai_col);
+            println!("The AI played {}", ai_col.describe());
+            if options.verbosity >= Verbosity::Normal {
+                print_diagnostics(&mctree);
+            }
+            println!("{}", board);
+            if !claimed && mctree.root_value() > options.claim_threshold {
+                claimed = true;
+                let pv: Vec<String> = mctree
+                    .root
+                    .principal_variation()
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect();
+                println!("The AI claims a win, expecting: {}", pv.join(" "));
+            }
+        } else {
+            let command = if options.ponder {
+                ponder(&mut mctree, &board, input)
+            } else {
+                get_command(&board, input)
+            };
+            let user_col = match command {
+                Some(Command::Play(m)) => m,
+                Some(Command::Save(path)) => {
+                    match save(&path, &moves, &board) {
+                        Ok(()) => println!("Saved the game to {}", path),
+                        Err(e) => println!("Could not save to {}: {}", path, e),
+                    }
+                    continue;
+                }
+                Some(Command::Quit) => break,
+                None => {
+                    println!("End of input");
+                    break;
+                }
+            };
+            board.do_action(user_col);
+            moves.push(user_col);
+            let reroot = mctree.do_action(user_col);
+            if options.verbosity >= Verbosity::Normal {
+                print_reroot(reroot);
+            }
+        }
+        let result = match mctree.is_terminal() {
+            Some(outcome) => result_message(&outcome),
+            None => continue,
+        };
+        if !ai_to_move {
+            println!("{}", board);
         }
-        if board.valid_actions(Player::P1).len() == 0 {
-            println!("Draw");
-            break;
+        println!("{}", result);
+        let line = board
+            .winning_macro_line(Player::P1)
+            .map(|line| ("X", line))
+            .or_else(|| board.winning_macro_line(Player::P2).map(|line| ("O", line)));
+        if let Some((winner, [a, b, c])) = line {
+            println!("{} won via boards {}-{}-{}", winner, a, b, c);
         }
+        break;
+    }
+}
+
+/// The `(macro, micro)` moves played to reach the benchmark position.
+const BENCH_MOVES: [(u8, u8); 8] = [(4, 4), (4, 0), (0, 4), (4, 8), (8, 4), (4, 2), (2, 4), (4, 6)];
+/// How many times the benchmark search is repeated; the reported numbers are averages.
+const BENCH_RUNS: usize = 5;
+
+/// Searches a fixed mid-game position for `iterations` iterations, several times over, and
+/// prints the average throughput and tree size as plain numbers.
+fn bench<R: Rng>(iterations: usize, mut rng: R) {
+    let mut board = T4Board::initial();
+    for &(macro_, micro) in BENCH_MOVES.iter() {
+        board.do_action(T4Move::new(macro_, micro));
+    }
+    let mut seconds = 0.0;
+    let mut tree_size = 0;
+    for _ in 0..BENCH_RUNS {
+        let to_move = board.next_player();
+        let mut mctree = MCTree::for_player_with_rng(board.clone(), to_move, &mut rng);
+        // Only the numbers below are printed, so that runs can be diffed.
+        mctree.set_verbosity(Verbosity::Silent);
+        let start = Instant::now();
+        mctree.search_n(iterations);
+        seconds += start.elapsed().as_secs_f64();
+        tree_size += mctree.root.size();
+    }
+    println!("iterations {}", iterations);
+    println!("playouts_per_sec {:.0}", (iterations * BENCH_RUNS) as f64 / seconds);
+    println!("avg_tree_size {}", tree_size / BENCH_RUNS);
+}
+
+fn run<R: Rng, I: BufRead + Send>(options: &Options, board: T4Board, rng: R, input: &mut I) {
+    match options.bench {
+        Some(iterations) => bench(iterations, rng),
+        None => mcts(options, board, rng, input),
     }
 }
 
 fn main() {
-    let thinking_time = env::args()
-        .nth(1)
-        .and_then(|a| usize::from_str(&a).ok())
-        .unwrap_or(3000);
-    mcts(thinking_time)
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("{}", USAGE);
+        process::exit(1)
+    });
+    let board = replay(&options.moves).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1)
+    });
+    // Not `stdin().lock()`, since pondering reads input on another thread.
+    let mut input = io::BufReader::new(io::stdin());
+    match options.seed {
+        Some(seed) => run(&options, board, seeded_rng(seed), &mut input),
+        None => run(&options, board, rand::thread_rng(), &mut input),
+    }
 }